@@ -1,10 +1,9 @@
 use std::cmp::Reverse;
-use std::error::Error;
 use std::fs;
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let input = fs::read_to_string("input/1")?;
+use anyhow::{Context, Error};
 
+fn parse_elves(input: &str) -> Result<Vec<u64>, Error> {
     let mut elves = Vec::new();
     let lines = input.lines();
     let mut elf = 0u64;
@@ -16,10 +15,56 @@ fn main() -> Result<(), Box<dyn Error>> {
             elf += l.parse::<u64>()?;
         }
     }
+    // The last elf is not followed by an empty line
+    if elf > 0 {
+        elves.push(elf);
+    }
 
+    Ok(elves)
+}
+
+fn elf_totals_sorted(input: &str) -> Result<Vec<u64>, Error> {
+    let mut elves = parse_elves(input)?;
     elves.sort_unstable_by_key(|e| Reverse(*e));
-    println!("Part 1: {}", elves.first().unwrap());
+
+    Ok(elves)
+}
+
+fn main() -> Result<(), Error> {
+    let input = fs::read_to_string("input/1")?;
+
+    let elves = elf_totals_sorted(&input)?;
+    println!("Part 1: {}", elves.first().context("no elves")?);
     println!("Part 2: {}", elves.iter().take(3).sum::<u64>());
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::elf_totals_sorted;
+
+    #[test]
+    fn sorted_totals_example() {
+        assert_eq!(
+            elf_totals_sorted(TEST_INPUT).unwrap(),
+            [24000, 11000, 10000, 6000, 4000]
+        );
+    }
+
+    static TEST_INPUT: &str = "1000
+2000
+3000
+
+4000
+
+5000
+6000
+
+7000
+8000
+9000
+
+10000
+";
+}
@@ -1,11 +1,19 @@
 use std::{error::Error, fs};
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum Move {
     Rock,
     Paper,
     Scissors,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Lose,
+    Draw,
+    Win,
+}
+
 const fn score_of(opponent: Move, player: Move) -> u64 {
     const LOSE_SCORE: u64 = 0;
     const DRAW_SCORE: u64 = 3;
@@ -44,20 +52,55 @@ fn part1_round_score(line: &str) -> u64 {
 }
 
 fn part2_round_score(line: &str) -> u64 {
-    match line {
-        "A X" => score_of(Move::Rock, Move::Scissors),
-        "A Y" => score_of(Move::Rock, Move::Rock),
-        "A Z" => score_of(Move::Rock, Move::Paper),
-        "B X" => score_of(Move::Paper, Move::Rock),
-        "B Y" => score_of(Move::Paper, Move::Paper),
-        "B Z" => score_of(Move::Paper, Move::Scissors),
-        "C X" => score_of(Move::Scissors, Move::Paper),
-        "C Y" => score_of(Move::Scissors, Move::Scissors),
-        "C Z" => score_of(Move::Scissors, Move::Rock),
-        _ => 0,
+    match decode_round(line) {
+        Some((opponent, outcome)) => score_of(opponent, player_for(opponent, outcome)),
+        None => 0,
+    }
+}
+
+// The move that gets the given outcome against the opponent
+const fn player_for(opponent: Move, outcome: Outcome) -> Move {
+    match (opponent, outcome) {
+        (_, Outcome::Draw) => opponent,
+        (Move::Rock, Outcome::Win) | (Move::Scissors, Outcome::Lose) => Move::Paper,
+        (Move::Paper, Outcome::Win) | (Move::Rock, Outcome::Lose) => Move::Scissors,
+        (Move::Scissors, Outcome::Win) | (Move::Paper, Outcome::Lose) => Move::Rock,
     }
 }
 
+// Inverse of decode_round, produces the part 2 style input line
+#[cfg(test)]
+fn encode_round(opponent: Move, outcome: Outcome) -> String {
+    let opponent = match opponent {
+        Move::Rock => 'A',
+        Move::Paper => 'B',
+        Move::Scissors => 'C',
+    };
+    let outcome = match outcome {
+        Outcome::Lose => 'X',
+        Outcome::Draw => 'Y',
+        Outcome::Win => 'Z',
+    };
+    format!("{} {}", opponent, outcome)
+}
+
+fn decode_round(line: &str) -> Option<(Move, Outcome)> {
+    let (opponent, outcome) = line.split_once(' ')?;
+    let opponent = match opponent {
+        "A" => Move::Rock,
+        "B" => Move::Paper,
+        "C" => Move::Scissors,
+        _ => return None,
+    };
+    let outcome = match outcome {
+        "X" => Outcome::Lose,
+        "Y" => Outcome::Draw,
+        "Z" => Outcome::Win,
+        _ => return None,
+    };
+    Some((opponent, outcome))
+}
+
 fn calculate_part(input: impl AsRef<str>, func: impl Fn(&str) -> u64) -> u64 {
     input.as_ref().lines().map(func).sum::<u64>()
 }
@@ -89,4 +132,24 @@ mod tests {
             12
         )
     }
+
+    #[test]
+    fn encode_round_trip() {
+        use super::{decode_round, encode_round, part2_round_score, Move, Outcome};
+
+        for opponent in [Move::Rock, Move::Paper, Move::Scissors] {
+            for (i, outcome) in [Outcome::Lose, Outcome::Draw, Outcome::Win]
+                .into_iter()
+                .enumerate()
+            {
+                let line = encode_round(opponent, outcome);
+                assert_eq!(decode_round(&line), Some((opponent, outcome)));
+
+                // Shapes score 1 to 3 on top of 0, 3 or 6 for the outcome
+                let score = part2_round_score(&line);
+                assert_eq!((score - 1) / 3, i as u64, "{}", line);
+            }
+        }
+        assert_eq!(encode_round(Move::Rock, Outcome::Draw), "A Y");
+    }
 }
@@ -1,3 +1,4 @@
+use std::fmt::Display;
 use std::fs;
 
 use anyhow::bail;
@@ -19,6 +20,15 @@ impl Instruction {
             let amount = amount_str.parse()?;
             let from = from_str.parse::<usize>()?;
             let to = to_str.parse::<usize>()?;
+            if amount == 0 {
+                bail!("move instruction '{}' moves no crates", line);
+            }
+            if from == to {
+                bail!(
+                    "move instruction '{}' moves crates onto the same stack",
+                    line
+                );
+            }
             Ok(Instruction { from, to, amount })
         } else {
             bail!("invalid move instruction {}", line)
@@ -33,6 +43,15 @@ struct CargoState {
 impl CargoState {
     fn parse(input: &str) -> Result<Self, Error> {
         let last_line = input.lines().last().context("no last line?")?;
+
+        let labels = last_line
+            .split_ascii_whitespace()
+            .map(|label| label.parse::<usize>())
+            .collect::<Result<Vec<_>, _>>()
+            .with_context(|| format!("invalid stack labels '{}'", last_line))?;
+        if !labels.iter().copied().eq(1..=labels.len()) {
+            bail!("stack labels '{}' are not 1 to {}", last_line, labels.len());
+        }
         let crates = last_line
             .char_indices()
             .filter(|(_, c)| c.is_ascii_digit())
@@ -67,6 +86,39 @@ impl CargoState {
         self.stacks[to].extend(yoink);
     }
 
+    // Tracks only the stack sizes, so nothing is moved if a later instruction turns out invalid
+    fn validate(&self, instructions: &[Instruction]) -> Result<(), Error> {
+        let mut sizes = self.stacks.iter().map(|s| s.len()).collect_vec();
+
+        for (i, inst) in instructions.iter().enumerate() {
+            for stack in [inst.from, inst.to] {
+                if !(1..=sizes.len()).contains(&stack) {
+                    bail!(
+                        "instruction at index {} refers to stack {}, but there are only {} stacks",
+                        i,
+                        stack,
+                        sizes.len()
+                    );
+                }
+            }
+
+            let available = sizes[inst.from - 1];
+            if available < inst.amount {
+                bail!(
+                    "instruction at index {} moves {} crates from stack {}, which only has {}",
+                    i,
+                    inst.amount,
+                    inst.from,
+                    available
+                );
+            }
+            sizes[inst.from - 1] -= inst.amount;
+            sizes[inst.to - 1] += inst.amount;
+        }
+
+        Ok(())
+    }
+
     fn get_code(&self) -> String {
         self.stacks
             .iter()
@@ -75,16 +127,47 @@ impl CargoState {
     }
 }
 
+impl Display for CargoState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let height = self.stacks.iter().map(|s| s.len()).max().unwrap_or(0);
+        for y in (0..height).rev() {
+            let row = self
+                .stacks
+                .iter()
+                .map(|stack| match stack.chars().nth(y) {
+                    Some(c) => format!("[{}]", c),
+                    None => "   ".to_string(),
+                })
+                .join(" ");
+            writeln!(f, "{}", row)?;
+        }
+
+        let base = (1..=self.stacks.len())
+            .map(|i| format!(" {} ", i))
+            .join(" ");
+        write!(f, "{}", base)
+    }
+}
+
 fn parse_input(input: &str) -> Result<(CargoState, Vec<Instruction>), Error> {
     let split_idx = input.find("\n\n").unwrap();
     let (setup, instructions) = input.split_at(split_idx + 1);
 
     let cargo = CargoState::parse(setup)?;
+    let line_offset = setup.lines().count();
     let instructions = instructions
         .lines()
-        .map(Instruction::parse)
-        .filter_map(Result::ok)
-        .collect_vec();
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            Instruction::parse(line).with_context(|| {
+                format!(
+                    "could not parse instruction on line {}",
+                    line_offset + i + 1
+                )
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
 
     Ok((cargo, instructions))
 }
@@ -94,6 +177,7 @@ fn run(
     apply_instruction: impl Fn(&mut CargoState, &Instruction),
 ) -> Result<String, Error> {
     let (mut cargo, instructions) = parse_input(input)?;
+    cargo.validate(&instructions)?;
 
     for inst in instructions {
         apply_instruction(&mut cargo, &inst);
@@ -127,6 +211,71 @@ mod tests {
         assert_eq!(inst.to, 3);
     }
 
+    #[test]
+    fn reject_noop_instructions() {
+        let err = super::Instruction::parse("move 0 from 1 to 2")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "move instruction 'move 0 from 1 to 2' moves no crates"
+        );
+
+        let err = super::Instruction::parse("move 3 from 2 to 2")
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "move instruction 'move 3 from 2 to 2' moves crates onto the same stack"
+        );
+
+        assert!(super::Instruction::parse("move -1 from 1 to 2").is_err());
+    }
+
+    #[test]
+    fn scrambled_labels() {
+        let input = TEST_INPUT.replace(" 1   2   3 ", " 1   3   2 ");
+        let err = super::parse_input(&input).err().unwrap();
+        assert_eq!(err.to_string(), "stack labels ' 1   3   2 ' are not 1 to 3");
+    }
+
+    #[test]
+    fn garbled_instruction() {
+        let input = TEST_INPUT.replace("move 3 from 1 to 3", "move three from 1 to 3");
+        let err = super::parse_input(&input).err().unwrap();
+        assert_eq!(err.to_string(), "could not parse instruction on line 7");
+    }
+
+    #[test]
+    fn validate_overdraw() {
+        let (cargo, instructions) = super::parse_input(TEST_INPUT).unwrap();
+        assert!(cargo.validate(&instructions).is_ok());
+
+        let input = TEST_INPUT.replace("move 2 from 2 to 1", "move 3 from 2 to 1");
+        let (cargo, instructions) = super::parse_input(&input).unwrap();
+        let err = cargo.validate(&instructions).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "instruction at index 2 moves 3 crates from stack 2, which only has 2"
+        );
+
+        let input = TEST_INPUT.replace("move 1 from 1 to 2", "move 1 from 1 to 4");
+        let (cargo, instructions) = super::parse_input(&input).unwrap();
+        assert!(cargo.validate(&instructions).is_err());
+    }
+
+    #[test]
+    fn display_initial_state() {
+        let (cargo, _) = super::parse_input(TEST_INPUT).unwrap();
+        let rendered = cargo.to_string();
+
+        let expected = TEST_INPUT.split("\n\n").next().unwrap();
+        assert_eq!(
+            rendered.lines().map(str::trim_end).collect::<Vec<_>>(),
+            expected.lines().map(str::trim_end).collect::<Vec<_>>()
+        );
+    }
+
     static TEST_INPUT: &str = "    [D]    
 [N] [C]    
 [Z] [M] [P]
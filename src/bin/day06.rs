@@ -1,24 +1,71 @@
+use std::collections::HashMap;
 use std::fs;
+use std::hash::Hash;
 
+use anyhow::bail;
 use anyhow::Context;
 use anyhow::Error;
 use itertools::Itertools;
 
-fn find_header<const N: usize>(input: &[char]) -> Result<usize, Error> {
-    input
-        .windows(N)
-        .enumerate()
-        .find(|(_, window)| window.iter().unique().count() == N)
-        .map(|(i, _)| i + N)
-        .context("could not find packet marker")
+// Rolling count of the items in the current window, so each item is only looked at twice
+fn find_header<T: Eq + Hash + Copy, const N: usize>(input: &[T]) -> Result<usize, Error> {
+    let mut counts: HashMap<T, usize> = HashMap::new();
+    for (i, &item) in input.iter().enumerate() {
+        *counts.entry(item).or_default() += 1;
+
+        if i >= N {
+            let old = input[i - N];
+            let count = counts
+                .get_mut(&old)
+                .context("window item missing from counts")?;
+            *count -= 1;
+            if *count == 0 {
+                counts.remove(&old);
+            }
+        }
+
+        if counts.len() == N {
+            return Ok(i + 1);
+        }
+    }
+
+    bail!("could not find packet marker")
+}
+
+// For each start, the shortest window starting there with n distinct items. The window end only
+// moves forward as the start does, so the same rolling counts work here.
+#[cfg(test)]
+fn first_distinct_window_lengths(input: &[u8], n: usize) -> Vec<Option<usize>> {
+    if n == 0 {
+        return vec![Some(0); input.len()];
+    }
+
+    let mut counts: HashMap<u8, usize> = HashMap::new();
+    let mut end = 0;
+    let mut out = Vec::with_capacity(input.len());
+    for (start, old) in input.iter().enumerate() {
+        while counts.len() < n && end < input.len() {
+            *counts.entry(input[end]).or_default() += 1;
+            end += 1;
+        }
+        out.push((counts.len() >= n).then_some(end - start));
+
+        let count = counts.entry(*old).or_default();
+        *count -= 1;
+        if *count == 0 {
+            counts.remove(old);
+        }
+    }
+
+    out
 }
 
 fn part1(input: &str) -> Result<usize, Error> {
-    find_header::<4>(&input.chars().collect_vec())
+    find_header::<_, 4>(&input.chars().collect_vec())
 }
 
 fn part2(input: &str) -> Result<usize, Error> {
-    find_header::<14>(&input.chars().collect_vec())
+    find_header::<_, 14>(&input.chars().collect_vec())
 }
 
 fn main() -> Result<(), Error> {
@@ -32,7 +79,7 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use super::{find_header, first_distinct_window_lengths, part1, part2};
     use rstest::rstest;
 
     #[rstest]
@@ -53,4 +100,29 @@ mod tests {
     fn part2_examples(#[case] input: &str, #[case] expected: usize) {
         assert_eq!(part2(input).unwrap(), expected);
     }
+
+    #[test]
+    fn distinct_window_lengths() {
+        assert_eq!(
+            first_distinct_window_lengths(b"aabbc", 2),
+            [Some(3), Some(2), Some(3), Some(2), None]
+        );
+        assert_eq!(
+            first_distinct_window_lengths(b"aabbc", 3),
+            [Some(5), Some(4), None, None, None]
+        );
+
+        // The first window of exactly N is the start-of-packet marker
+        let input = "mjqjpqmgbljsphdztnvjfqwrcgsmlb";
+        let lengths = first_distinct_window_lengths(input.as_bytes(), 4);
+        let marker_start = lengths.iter().position(|&l| l == Some(4)).unwrap();
+        assert_eq!(marker_start + 4, part1(input).unwrap());
+    }
+
+    #[test]
+    fn generic_tokens() {
+        let tokens: &[u32] = &[1, 1, 2, 2, 3, 1000, 1000, 5];
+        assert_eq!(find_header::<_, 3>(tokens).unwrap(), 6);
+        assert!(find_header::<_, 5>(tokens).is_err());
+    }
 }
@@ -5,6 +5,9 @@ use std::iter::Peekable;
 use anyhow::{bail, Context, Error};
 use itertools::Itertools;
 
+const TOTAL_SPACE: u64 = 70000000;
+const REQUIRED_SPACE: u64 = 30000000;
+
 #[derive(Debug)]
 enum Node<'a> {
     Directory {
@@ -29,20 +32,28 @@ impl<'a> Node<'a> {
         while let Some(line) = lines.next() {
             match line.split_ascii_whitespace().collect_vec().as_slice() {
                 ["$", "ls"] => {
-                    let file_items = lines
+                    // Listing runs until the next command, blank lines are not part of it
+                    let entries = lines
                         .peeking_take_while(|line| !line.starts_with('$'))
-                        .filter(|line| !line.starts_with("dir"))
-                        .filter_map(|line| line.split_once(' '));
-
-                    for (size, name) in file_items {
-                        items.insert(
-                            name,
-                            Self::File {
-                                size: size.parse()?,
-                            },
-                        );
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty());
+
+                    for line in entries {
+                        // Only the first space separates the size, names may contain more
+                        match line.split_once(' ') {
+                            // Directories are only added once we cd into them
+                            Some(("dir", name)) if !name.is_empty() => {}
+                            Some((size, name)) if !name.is_empty() => {
+                                let size = size
+                                    .parse()
+                                    .with_context(|| format!("invalid file size in '{}'", line))?;
+                                items.insert(name, Self::File { size });
+                            }
+                            _ => bail!("could not parse ls entry '{}'", line),
+                        }
                     }
                 }
+                [] => continue,
                 ["$", "cd", ".."] => break,
                 ["$", "cd", "/"] => bail!("did not expect to go to root oops"),
                 ["$", "cd", rel] => {
@@ -84,6 +95,26 @@ impl<'a> Node<'a> {
         inner(self, "/".to_string(), &mut out);
         out
     }
+
+    // (used, free, need to free) for a disk of the given size
+    fn disk_report(&self, total_space: u64, required_space: u64) -> (u64, u64, u64) {
+        let used = *self.size();
+        let free = total_space.saturating_sub(used);
+        let need_to_free = required_space.saturating_sub(free);
+
+        (used, free, need_to_free)
+    }
+
+    // Directories large enough that deleting them frees up the required space, smallest first
+    fn deletion_candidates(&self) -> Vec<(String, u64)> {
+        let (_, _, min_size) = self.disk_report(TOTAL_SPACE, REQUIRED_SPACE);
+
+        self.dir_sizes()
+            .into_iter()
+            .filter(|&(_, size)| size >= min_size)
+            .sorted_by_key(|&(_, size)| size)
+            .collect()
+    }
 }
 
 fn part1(input: &str) -> Result<u64, Error> {
@@ -101,17 +132,10 @@ fn part1(input: &str) -> Result<u64, Error> {
 fn part2(input: &str) -> Result<u64, Error> {
     let root = Node::parse_root(input)?;
 
-    let sizes = root.dir_sizes();
-
-    const TOTAL_SPACE: u64 = 70000000;
-    const REQUIRED_SPACE: u64 = 30000000;
-    let used_space = sizes[&"/".to_string()];
-    let min_size = REQUIRED_SPACE - (TOTAL_SPACE - used_space);
-
-    let min = *sizes
-        .values()
-        .filter(|&&size| size >= min_size)
-        .min()
+    let (_, min) = root
+        .deletion_candidates()
+        .into_iter()
+        .next()
         .context("no min value found?")?;
 
     Ok(min)
@@ -128,7 +152,7 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use super::{part1, part2, Node, REQUIRED_SPACE, TOTAL_SPACE};
 
     static TEST_INPUT: &str = "$ cd /
 $ ls
@@ -163,4 +187,69 @@ $ ls
     fn part2_example() {
         assert_eq!(part2(TEST_INPUT).unwrap(), 24933642);
     }
+
+    #[test]
+    fn blank_lines_in_listing() {
+        let input = TEST_INPUT.replace("dir e\n29116 f", "dir e\n\n29116 f\n");
+        assert_eq!(part1(&input).unwrap(), 95437);
+        assert_eq!(part2(&input).unwrap(), 24933642);
+
+        let input = TEST_INPUT.replace("29116 f", "29116f");
+        assert!(part1(&input).is_err());
+    }
+
+    #[test]
+    fn malformed_listing_entry() {
+        for (from, to) in [
+            ("29116 f", "29116f"),
+            ("dir e", "dir"),
+            ("dir e", "directory e"),
+            ("2557 g", "2557 "),
+        ] {
+            let input = TEST_INPUT.replace(from, to);
+            let err = part1(&input).unwrap_err();
+            assert!(
+                format!("{:#}", err).contains(to.trim()),
+                "'{}' gave: {:#}",
+                to,
+                err
+            );
+        }
+    }
+
+    fn child<'a>(node: &'a Node<'a>, name: &str) -> &'a Node<'a> {
+        match node {
+            Node::Directory { items, .. } => &items[name],
+            Node::File { .. } => panic!("'{}' is not in a directory", name),
+        }
+    }
+
+    #[test]
+    fn filename_with_spaces() {
+        let input = TEST_INPUT.replace("584 i", "584 my file.txt");
+        let root = Node::parse_root(&input).unwrap();
+
+        let file = child(child(child(&root, "a"), "e"), "my file.txt");
+        assert!(matches!(file, Node::File { size: 584 }));
+        assert_eq!(part1(&input).unwrap(), 95437);
+    }
+
+    #[test]
+    fn disk_report_example() {
+        let root = Node::parse_root(TEST_INPUT).unwrap();
+        assert_eq!(
+            root.disk_report(TOTAL_SPACE, REQUIRED_SPACE),
+            (48381165, 21618835, 8381165)
+        );
+        assert_eq!(root.disk_report(50000000, 1000000), (48381165, 1618835, 0));
+    }
+
+    #[test]
+    fn deletion_candidates_example() {
+        let root = Node::parse_root(TEST_INPUT).unwrap();
+        let candidates = root.deletion_candidates();
+
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[0], ("/d/".to_string(), 24933642));
+    }
 }
@@ -6,21 +6,49 @@ use std::{
 };
 
 fn parse_heights(input: &str) -> Result<Array2<usize>, Error> {
+    parse_heights_with(input, |c| c.to_digit(10).map(|d| d as usize))
+}
+
+// Indexed by (row, column)
+fn parse_heights_with(
+    input: &str,
+    decode: impl Fn(char) -> Option<usize>,
+) -> Result<Array2<usize>, Error> {
     let mut trees_vec = Vec::new();
-    let max_x = input.lines().next().unwrap().chars().count();
-    let max_y = input.lines().count();
+    let columns = input
+        .lines()
+        .next()
+        .context("input contains no lines")?
+        .chars()
+        .count();
+    let rows = input.lines().count();
     for line in input.lines() {
         for c in line.chars() {
-            let height = c
-                .to_digit(10)
-                .with_context(|| format!("could not convert char '{}' to tree height", c))?
-                as usize;
+            let height = decode(c)
+                .with_context(|| format!("could not convert char '{}' to tree height", c))?;
 
             trees_vec.push(height);
         }
     }
 
-    Ok(Array2::from_shape_vec((max_x, max_y), trees_vec)?)
+    Ok(Array2::from_shape_vec((rows, columns), trees_vec)?)
+}
+
+fn sweep_axis(
+    visiblity: &mut impl IndexMut<usize, Output = bool>,
+    height: &impl Index<usize, Output = usize>,
+    mut r: impl Iterator<Item = usize>,
+) {
+    let start = r.next().unwrap();
+    visiblity[start] = true;
+    let mut max_previous = height[start];
+    for i in r {
+        let other = height[i];
+        if other > max_previous {
+            visiblity[i] = true;
+            max_previous = other;
+        }
+    }
 }
 
 fn calculate_visibility(heights: &Array2<usize>) -> Result<Array2<bool>, Error> {
@@ -28,23 +56,6 @@ fn calculate_visibility(heights: &Array2<usize>) -> Result<Array2<bool>, Error>
     let mut visibility = Array2::default([shape[0], shape[1]]);
     for axis in [Axis(0), Axis(1)] {
         for (axis_idx, height_axis) in heights.axis_iter(axis).enumerate() {
-            fn sweep_axis(
-                visiblity: &mut impl IndexMut<usize, Output = bool>,
-                height: &impl Index<usize, Output = usize>,
-                mut r: impl Iterator<Item = usize>,
-            ) {
-                let start = r.next().unwrap();
-                visiblity[start] = true;
-                let mut max_previous = height[start];
-                for i in r {
-                    let other = height[i];
-                    if other > max_previous {
-                        visiblity[i] = true;
-                        max_previous = other;
-                    }
-                }
-            }
-
             let axis_len = height_axis.len();
             let mut visibility_axis = visibility.index_axis_mut(axis, axis_idx);
             sweep_axis(&mut visibility_axis, &height_axis, 0..axis_len);
@@ -55,6 +66,108 @@ fn calculate_visibility(heights: &Array2<usize>) -> Result<Array2<bool>, Error>
     Ok(visibility)
 }
 
+// Like calculate_visibility, but looking in from the corners along both diagonals
+#[cfg(test)]
+fn calculate_diagonal_visibility(heights: &Array2<usize>) -> Array2<bool> {
+    let (rows, columns) = heights.dim();
+    let mut visibility = Array2::default((rows, columns));
+
+    // Every diagonal starts on the top row or on the left/right column below it
+    let down_right = (0..columns)
+        .map(|c| (0, c))
+        .chain((1..rows).map(|r| (r, 0)))
+        .map(|(r, c)| (r, c, 1));
+    let down_left = (0..columns)
+        .map(|c| (0, c))
+        .chain((1..rows).map(|r| (r, columns - 1)))
+        .map(|(r, c)| (r, c, -1));
+
+    for (r, c, dc) in down_right.chain(down_left) {
+        let cells = (0..)
+            .map(|i| (r + i, c as isize + dc * i as isize))
+            .take_while(|&(r, c)| r < rows && 0 <= c && c < columns as isize)
+            .map(|(r, c)| (r, c as usize))
+            .collect::<Vec<_>>();
+        let height_diagonal = cells.iter().map(|&p| heights[p]).collect::<Vec<_>>();
+
+        let mut visibility_diagonal = vec![false; cells.len()];
+        sweep_axis(&mut visibility_diagonal, &height_diagonal, 0..cells.len());
+        sweep_axis(
+            &mut visibility_diagonal,
+            &height_diagonal,
+            (0..cells.len()).rev(),
+        );
+        for (&p, visible) in cells.iter().zip(visibility_diagonal) {
+            visibility[p] |= visible;
+        }
+    }
+
+    visibility
+}
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy)]
+enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+// Trees visible to someone standing outside the given edge, looking along row/column `line`
+#[cfg(test)]
+fn visible_from_edge(heights: &Array2<usize>, edge: Edge, line: usize) -> Vec<(usize, usize)> {
+    let axis = match edge {
+        Edge::Left | Edge::Right => Axis(0),
+        Edge::Top | Edge::Bottom => Axis(1),
+    };
+    let height_axis = heights.index_axis(axis, line);
+    let axis_len = height_axis.len();
+
+    let mut visibility = vec![false; axis_len];
+    match edge {
+        Edge::Left | Edge::Top => sweep_axis(&mut visibility, &height_axis, 0..axis_len),
+        Edge::Right | Edge::Bottom => {
+            sweep_axis(&mut visibility, &height_axis, (0..axis_len).rev())
+        }
+    }
+
+    let visible = visibility
+        .iter()
+        .enumerate()
+        .filter(|(_, &visible)| visible)
+        .map(|(i, _)| match axis {
+            Axis(0) => (line, i),
+            _ => (i, line),
+        });
+
+    match edge {
+        Edge::Left | Edge::Top => visible.collect(),
+        Edge::Right | Edge::Bottom => visible.rev().collect(),
+    }
+}
+
+// Per cell, the tallest tree from the start of its lane along `axis` up to and including itself.
+// With `reverse`, lanes start from the far end instead.
+#[cfg(test)]
+fn running_max_from(heights: &Array2<usize>, axis: Axis, reverse: bool) -> Array2<usize> {
+    let mut out = heights.clone();
+    for mut lane in out.lanes_mut(axis) {
+        let mut max = 0;
+        let update = |h: &mut usize| {
+            max = max.max(*h);
+            *h = max;
+        };
+        if reverse {
+            lane.iter_mut().rev().for_each(update);
+        } else {
+            lane.iter_mut().for_each(update);
+        }
+    }
+
+    out
+}
+
 fn calculate_scores(heights: &Array2<usize>) -> Result<Array2<usize>, Error> {
     let shape = heights.shape();
     let mut scores = Vec::with_capacity(heights.iter().count());
@@ -89,6 +202,7 @@ fn calculate_scores(heights: &Array2<usize>) -> Result<Array2<usize>, Error> {
     Ok(Array2::from_shape_vec([shape[0], shape[1]], scores)?)
 }
 
+#[cfg(test)]
 fn part1(input: &str) -> Result<usize, Error> {
     let forest = parse_heights(input)?;
     let visibility = calculate_visibility(&forest)?;
@@ -96,6 +210,7 @@ fn part1(input: &str) -> Result<usize, Error> {
     Ok(visibility.iter().filter(|&&visible| visible).count())
 }
 
+#[cfg(test)]
 fn part2(input: &str) -> Result<usize, Error> {
     let forest = parse_heights(input)?;
     let scores = calculate_scores(&forest)?;
@@ -103,18 +218,37 @@ fn part2(input: &str) -> Result<usize, Error> {
     scores.iter().max().copied().context("could not find max")
 }
 
+// Both parts with a single parse
+fn analyze(input: &str) -> Result<(usize, usize), Error> {
+    let forest = parse_heights(input)?;
+
+    let visibility = calculate_visibility(&forest)?;
+    let visible = visibility.iter().filter(|&&visible| visible).count();
+
+    let scores = calculate_scores(&forest)?;
+    let best_score = scores.iter().max().copied().context("could not find max")?;
+
+    Ok((visible, best_score))
+}
+
 fn main() -> Result<(), Error> {
     let input = fs::read_to_string("input/8")?;
 
-    println!("Part 1: {}", part1(&input)?);
-    println!("Part 2: {}", part2(&input)?);
+    let (visible, best_score) = analyze(&input)?;
+    println!("Part 1: {}", visible);
+    println!("Part 2: {}", best_score);
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use ndarray::{array, Axis};
+
+    use super::{
+        analyze, calculate_diagonal_visibility, calculate_visibility, parse_heights,
+        parse_heights_with, part1, part2, running_max_from, visible_from_edge, Edge,
+    };
 
     static TEST_INPUT: &str = "30373
 25512
@@ -123,6 +257,62 @@ mod tests {
 35390
 ";
 
+    #[test]
+    fn letter_heights() {
+        let decode = |c: char| ('a'..='j').position(|l| l == c);
+        let letters = "dadhd\ncffbc\ngfddc\nddfej\ndfdja\n";
+
+        assert_eq!(
+            parse_heights_with(letters, decode).unwrap(),
+            parse_heights(TEST_INPUT).unwrap()
+        );
+        assert!(parse_heights_with("abk\n", decode).is_err());
+
+        let wide = parse_heights_with("abc\ndef\n", decode).unwrap();
+        assert_eq!(wide.dim(), (2, 3));
+        assert_eq!(wide[(1, 0)], 3);
+    }
+
+    #[test]
+    fn running_max_example() {
+        let heights = parse_heights(TEST_INPUT).unwrap();
+
+        let along_rows = running_max_from(&heights, Axis(1), false);
+        assert_eq!(along_rows[(0, 2)], 3);
+        assert_eq!(along_rows[(0, 4)], 7);
+
+        let along_columns = running_max_from(&heights, Axis(0), false);
+        assert_eq!(along_columns[(2, 0)], 6);
+        assert_eq!(along_columns[(4, 0)], 6);
+
+        let along_rows_reversed = running_max_from(&heights, Axis(1), true);
+        assert_eq!(along_rows_reversed[(1, 0)], 5);
+        assert_eq!(along_rows_reversed[(1, 3)], 2);
+    }
+
+    #[test]
+    fn diagonal_visibility() {
+        // The centre is in plain sight from the edges, but hidden behind every corner
+        let mut heights = array![[5, 0, 5], [0, 3, 0], [5, 0, 5]];
+        assert!(calculate_visibility(&heights).unwrap()[(1, 1)]);
+        assert!(!calculate_diagonal_visibility(&heights)[(1, 1)]);
+
+        // Until one corner is lowered
+        heights[(2, 2)] = 1;
+        let visibility = calculate_diagonal_visibility(&heights);
+        assert!(visibility[(1, 1)]);
+        // Edges are always visible along some diagonal
+        assert_eq!(visibility.iter().filter(|&&v| v).count(), 9);
+
+        let heights = parse_heights(TEST_INPUT).unwrap();
+        let visibility = calculate_diagonal_visibility(&heights);
+        assert_eq!(visibility.iter().filter(|&&v| v).count(), 16 + 6);
+        // The interior 1, and two 3s with a 3 or higher on every diagonal
+        for hidden in [(1, 3), (2, 2), (3, 1)] {
+            assert!(!visibility[hidden]);
+        }
+    }
+
     #[test]
     fn part1_example() {
         assert_eq!(part1(TEST_INPUT).unwrap(), 21);
@@ -132,4 +322,25 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2(TEST_INPUT).unwrap(), 8);
     }
+
+    #[test]
+    fn analyze_example() {
+        assert_eq!(analyze(TEST_INPUT).unwrap(), (21, 8));
+    }
+
+    #[test]
+    fn visible_from_edges() {
+        let heights = parse_heights(TEST_INPUT).unwrap();
+
+        assert_eq!(visible_from_edge(&heights, Edge::Left, 2), [(2, 0)]);
+        assert_eq!(
+            visible_from_edge(&heights, Edge::Right, 2),
+            [(2, 4), (2, 3), (2, 1), (2, 0)]
+        );
+        assert_eq!(visible_from_edge(&heights, Edge::Top, 2), [(0, 2), (1, 2)]);
+        assert_eq!(
+            visible_from_edge(&heights, Edge::Bottom, 2),
+            [(4, 2), (3, 2)]
+        );
+    }
 }
@@ -1,40 +1,128 @@
-use anyhow::{Context, Error};
-use std::{collections::HashSet, fs};
+use anyhow::{bail, Context, Error};
+use std::{collections::HashSet, fs, str::FromStr};
 
-fn run_simulation<const N: usize>(input: &str) -> Result<HashSet<(i32, i32)>, Error> {
-    let mut rope = [(0i32, 0i32); N];
+type Visited = HashSet<(i32, i32)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Left,
+    Right,
+    Down,
+    Up,
+}
+
+impl Direction {
+    fn delta(&self) -> (i32, i32) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Down => (0, -1),
+            Direction::Up => (0, 1),
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "L" => Ok(Direction::Left),
+            "R" => Ok(Direction::Right),
+            "D" => Ok(Direction::Down),
+            "U" => Ok(Direction::Up),
+            e => bail!("could not parse '{}' as a direction", e),
+        }
+    }
+}
+
+fn run_simulation<const N: usize>(input: &str) -> Result<Visited, Error> {
+    tail_visited(input, &mut [(0, 0); N])
+}
+
+// Positions visited by the last knot of a rope starting at the origin
+fn tail_visited(input: &str, rope: &mut [(i32, i32)]) -> Result<Visited, Error> {
+    let mut visited = HashSet::from([(0, 0)]);
+    walk_rope(input, rope, |rope, tail_moved| {
+        if tail_moved {
+            visited.insert(rope[rope.len() - 1]);
+        }
+    })?;
+
+    Ok(visited)
+}
+
+// Returns the positions visited by the tail and by the head, in that order
+#[cfg(test)]
+fn run_simulation_from<const N: usize>(
+    input: &str,
+    start: (i32, i32),
+) -> Result<(Visited, Visited), Error> {
     let mut visited = HashSet::new();
-    visited.insert((0, 0));
+    visited.insert(start);
+    let mut head_visited = HashSet::new();
+    head_visited.insert(start);
+
+    walk_rope(input, &mut [start; N], |rope, tail_moved| {
+        head_visited.insert(rope[0]);
+        if tail_moved {
+            visited.insert(rope[N - 1]);
+        }
+    })?;
+
+    Ok((visited, head_visited))
+}
 
+// (min x, max x, min y, max y) visited by the tail
+#[cfg(test)]
+fn tail_extent<const N: usize>(input: &str) -> Result<(i32, i32, i32, i32), Error> {
+    let mut extent = (0, 0, 0, 0);
+    walk_rope(input, &mut [(0, 0); N], |rope, _| {
+        let (x, y) = rope[N - 1];
+        extent.0 = extent.0.min(x);
+        extent.1 = extent.1.max(x);
+        extent.2 = extent.2.min(y);
+        extent.3 = extent.3.max(y);
+    })?;
+
+    Ok(extent)
+}
+
+// Like run_simulation, but with the rope length only known at runtime
+#[cfg(test)]
+fn run_simulation_dynamic(input: &str, knots: usize) -> Result<Visited, Error> {
+    if knots == 0 {
+        bail!("a rope needs at least one knot");
+    }
+
+    tail_visited(input, &mut vec![(0, 0); knots])
+}
+
+// Calls `step` after every single step of the head, along with whether the tail moved
+fn walk_rope(
+    input: &str,
+    rope: &mut [(i32, i32)],
+    mut step: impl FnMut(&[(i32, i32)], bool),
+) -> Result<(), Error> {
     for line in input.lines() {
         let (dir, amount) = line.split_once(' ').context("could not split input")?;
+        let (dx, dy) = dir.parse::<Direction>()?.delta();
         let amount = amount.parse::<i32>()?;
 
         for _ in 0..amount {
-            let head = &mut rope[0];
-            move_head(dir, head);
-            if simulate_rope(&mut rope) {
-                visited.insert(rope[N - 1]);
-            }
+            rope[0].0 += dx;
+            rope[0].1 += dy;
+            let tail_moved = simulate_rope(rope);
+            step(rope, tail_moved);
         }
     }
 
-    Ok(visited)
-}
-
-fn move_head(dir: &str, head: &mut (i32, i32)) {
-    match dir {
-        "L" => head.0 -= 1,
-        "R" => head.0 += 1,
-        "D" => head.1 -= 1,
-        "U" => head.1 += 1,
-        _ => {}
-    }
+    Ok(())
 }
 
 // returns true if we moved the tail of the rope
-fn simulate_rope<const N: usize>(rope: &mut [(i32, i32); N]) -> bool {
-    for i in 0..N - 1 {
+fn simulate_rope(rope: &mut [(i32, i32)]) -> bool {
+    for i in 0..rope.len() - 1 {
         let h = rope[i];
         let t = &mut rope[i + 1];
 
@@ -68,7 +156,12 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use itertools::Itertools;
+
+    use super::{
+        part1, part2, run_simulation, run_simulation_dynamic, run_simulation_from, tail_extent,
+        Direction,
+    };
 
     static TEST_INPUT_1: &str = "R 4
 U 4
@@ -96,4 +189,87 @@ U 20";
     fn part2_example() {
         assert_eq!(part2(TEST_INPUT_2).unwrap(), 36);
     }
+
+    #[test]
+    fn parse_direction() {
+        let up = "U".parse::<Direction>().unwrap();
+        assert_eq!(up, Direction::Up);
+        assert_eq!(up.delta(), (0, 1));
+
+        let err = "X".parse::<Direction>().unwrap_err();
+        assert_eq!(err.to_string(), "could not parse 'X' as a direction");
+        assert!(part1("R 4\nX 2\n").is_err());
+    }
+
+    #[test]
+    fn translated_start() {
+        let (tail, head) = run_simulation_from::<10>(TEST_INPUT_2, (0, 0)).unwrap();
+        let (moved_tail, moved_head) = run_simulation_from::<10>(TEST_INPUT_2, (100, -50)).unwrap();
+
+        assert_eq!(moved_tail.len(), 36);
+        assert_eq!(moved_tail.len(), tail.len());
+        assert_eq!(moved_head.len(), head.len());
+        assert!(moved_head.contains(&(100 - 3, -50 + 8)));
+    }
+
+    #[test]
+    fn dynamic_length() {
+        assert_eq!(run_simulation_dynamic(TEST_INPUT_1, 2).unwrap().len(), 13);
+        assert_eq!(run_simulation_dynamic(TEST_INPUT_2, 10).unwrap().len(), 36);
+
+        let err = run_simulation_dynamic(TEST_INPUT_1, 0).unwrap_err();
+        assert_eq!(err.to_string(), "a rope needs at least one knot");
+    }
+
+    #[test]
+    fn longer_rope_stays_within_shorter() {
+        // Every knot only ever moves towards the one in front of it, so it can never leave the
+        // area the knot in front has covered
+
+        // LCG, no need to pull in a dependency for this
+        let mut seed = 98765u64;
+        let mut next = |n: u64| {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+            (seed >> 33) % n
+        };
+
+        for _ in 0..100 {
+            let input = (0..30)
+                .map(|_| {
+                    format!(
+                        "{} {}",
+                        ["L", "R", "U", "D"][next(4) as usize],
+                        next(10) + 1
+                    )
+                })
+                .join("\n");
+
+            let short = run_simulation_dynamic(&input, 2).unwrap();
+            let long = run_simulation_dynamic(&input, 10).unwrap();
+
+            let (min_x, max_x) = short.iter().map(|p| p.0).minmax().into_option().unwrap();
+            let (min_y, max_y) = short.iter().map(|p| p.1).minmax().into_option().unwrap();
+            assert!(
+                long.iter()
+                    .all(|&(x, y)| (min_x..=max_x).contains(&x) && (min_y..=max_y).contains(&y)),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn tail_extent_matches_visited() {
+        let visited = run_simulation::<10>(TEST_INPUT_2).unwrap();
+        let xs = visited.iter().map(|p| p.0);
+        let ys = visited.iter().map(|p| p.1);
+        let expected = (
+            xs.clone().min().unwrap(),
+            xs.max().unwrap(),
+            ys.clone().min().unwrap(),
+            ys.max().unwrap(),
+        );
+
+        assert_eq!(tail_extent::<10>(TEST_INPUT_2).unwrap(), expected);
+    }
 }
@@ -1,18 +1,24 @@
-use anyhow::{bail, Error};
+use anyhow::{bail, Context, Error};
 use itertools::Itertools;
 use std::{fs, iter};
 
 enum Instruction {
     Noop,
     Addx(i64),
+    // Not part of the puzzle, sets x back to its initial value
+    Reset,
 }
 
 impl Instruction {
     fn parse(line: &str) -> Result<Self, Error> {
         let parts = line.split(' ').collect_vec();
         let inst = match parts.as_slice() {
-            ["noop", ..] => Instruction::Noop,
-            ["addx", v] => Instruction::Addx(v.parse()?),
+            ["noop"] => Instruction::Noop,
+            ["reset"] => Instruction::Reset,
+            ["addx", v] => Instruction::Addx(
+                v.parse()
+                    .with_context(|| format!("invalid addx operand in '{}'", line))?,
+            ),
             _ => bail!("cannot parse instruction '{}'", line),
         };
 
@@ -21,7 +27,7 @@ impl Instruction {
 
     fn cycles(&self) -> usize {
         match self {
-            Instruction::Noop => 1,
+            Instruction::Noop | Instruction::Reset => 1,
             Instruction::Addx(_) => 2,
         }
     }
@@ -33,22 +39,34 @@ fn simulate_machine(input: &str) -> Result<Vec<i64>, Error> {
     for line in input.lines() {
         let instruction = Instruction::parse(line)?;
 
-        out.extend(iter::repeat(x).take(instruction.cycles()));
+        out.extend(iter::repeat_n(x, instruction.cycles()));
 
         match instruction {
             Instruction::Noop => {}
             Instruction::Addx(val) => x += val,
+            Instruction::Reset => x = 1,
         }
     }
 
     Ok(out)
 }
 
-fn part1(input: &str) -> Result<i64, Error> {
+// x * cycle for every cycle, cycles counting from 1
+fn signal_strengths(input: &str) -> Result<Vec<i64>, Error> {
     let x_values = simulate_machine(input)?;
 
+    Ok(x_values
+        .iter()
+        .enumerate()
+        .map(|(i, x)| x * (1 + i as i64))
+        .collect())
+}
+
+fn part1(input: &str) -> Result<i64, Error> {
+    let strengths = signal_strengths(input)?;
+
     let sum = [19, 59, 99, 139, 179, 219]
-        .map(|i| x_values[i] * (1 + i as i64))
+        .map(|i| strengths[i])
         .iter()
         .sum();
 
@@ -72,6 +90,22 @@ fn part2(input: &str) -> Result<String, Error> {
     Ok(output)
 }
 
+// Pixels that differ between two renderings, a pixel missing from one of them counts as differing
+#[cfg(test)]
+fn crt_diff(a: &str, b: &str) -> usize {
+    a.lines()
+        .zip_longest(b.lines())
+        .map(|lines| {
+            let (a, b) = lines.or_default();
+            a.trim_end()
+                .chars()
+                .zip_longest(b.trim_end().chars())
+                .filter(|pixels| !matches!(pixels, itertools::EitherOrBoth::Both(a, b) if a == b))
+                .count()
+        })
+        .sum()
+}
+
 fn main() -> Result<(), Error> {
     let input = fs::read_to_string("input/10")?;
 
@@ -83,13 +117,51 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use itertools::Itertools;
+
+    use super::{crt_diff, part1, part2, signal_strengths, simulate_machine, Instruction};
+    use rstest::rstest;
 
     #[test]
     fn part1_example() {
         assert_eq!(part1(TEST_INPUT).unwrap(), 13140);
     }
 
+    #[test]
+    fn signal_strengths_example() {
+        let strengths = signal_strengths(TEST_INPUT).unwrap();
+        let cycles = TEST_INPUT
+            .lines()
+            .map(|l| if l == "noop" { 1 } else { 2 })
+            .sum::<usize>();
+
+        assert_eq!(strengths.len(), cycles);
+        assert_eq!(strengths[19], 420);
+        assert_eq!(
+            [20, 60, 100, 140, 180, 220]
+                .map(|cycle| strengths[cycle - 1])
+                .iter()
+                .sum::<i64>(),
+            13140
+        );
+    }
+
+    #[test]
+    fn reset_instruction() {
+        let program = "addx 5\nreset\naddx 3\nnoop\nreset\nnoop";
+        assert_eq!(simulate_machine(program).unwrap(), [1, 1, 6, 1, 1, 4, 4, 1]);
+    }
+
+    #[rstest]
+    #[case("noop x")]
+    #[case("reset 1")]
+    #[case("addx")]
+    #[case("addx foo")]
+    #[case("addx 1 2")]
+    fn invalid_instructions(#[case] line: &str) {
+        assert!(Instruction::parse(line).is_err());
+    }
+
     static PART2_EXPECTED: &str = "##..##..##..##..##..##..##..##..##..##..
 ###...###...###...###...###...###...###.
 ####....####....####....####....####....
@@ -103,6 +175,21 @@ mod tests {
         assert_eq!(part2(TEST_INPUT).unwrap(), PART2_EXPECTED);
     }
 
+    #[test]
+    fn crt_pixel_diff() {
+        assert_eq!(crt_diff(PART2_EXPECTED, PART2_EXPECTED), 0);
+
+        let mut changed = PART2_EXPECTED.to_string();
+        changed.replace_range(0..1, ".");
+        assert_eq!(crt_diff(PART2_EXPECTED, &changed), 1);
+
+        let padded = PART2_EXPECTED.replace('\n', "  \n");
+        assert_eq!(crt_diff(PART2_EXPECTED, &padded), 0);
+
+        let last_row_missing = PART2_EXPECTED.lines().take(5).join("\n");
+        assert_eq!(crt_diff(PART2_EXPECTED, &last_row_missing), 40);
+    }
+
     static TEST_INPUT: &str = "addx 15
 addx -11
 addx 6
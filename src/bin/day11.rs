@@ -1,4 +1,5 @@
-use anyhow::{anyhow, Error};
+use anyhow::{anyhow, bail, Context, Error};
+use itertools::Itertools;
 use nom::{
     branch::alt,
     bytes::complete::tag,
@@ -8,10 +9,11 @@ use nom::{
     sequence::tuple,
     IResult,
 };
-use std::{cell::RefCell, fs};
+use std::{cell::RefCell, cmp::Reverse, collections::VecDeque, fmt::Display, fs};
 
 struct Monkey {
-    items: Vec<u64>,
+    // Items are thrown in the order they were received
+    items: VecDeque<u64>,
 
     arg1: Option<u64>,
     arg2: Option<u64>,
@@ -22,28 +24,74 @@ struct Monkey {
     if_false: usize,
 }
 
-#[derive(Clone)]
+impl Monkey {
+    fn inspect(&self, item: u64) -> Result<u64, Error> {
+        match self.operation {
+            Operation::Square => self.operation.eval(item, item),
+            _ => self
+                .operation
+                .eval(self.arg1.unwrap_or(item), self.arg2.unwrap_or(item)),
+        }
+    }
+}
+
+// e.g. `Monkey[items=[79,98], op=*19, test=%23 ->2/3]`, with the left-hand `old` left out
+impl Display for Monkey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self.operation {
+            Operation::Add => '+',
+            Operation::Subtract => '-',
+            Operation::Multiply | Operation::Square => '*',
+        };
+        let arg1 = self.arg1.map(|a| a.to_string()).unwrap_or_default();
+        let arg2 = self
+            .arg2
+            .map(|a| a.to_string())
+            .unwrap_or_else(|| "old".to_string());
+
+        write!(
+            f,
+            "Monkey[items=[{}], op={}{}{}, test=%{} ->{}/{}]",
+            self.items.iter().join(","),
+            arg1,
+            op,
+            arg2,
+            self.test,
+            self.if_true,
+            self.if_false
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 enum Operation {
     Add,
+    Subtract,
     Multiply,
+    // `old * old`, both args are `None`
+    Square,
 }
 
 impl Operation {
-    fn eval(&self, a: u64, b: u64) -> u64 {
-        match self {
-            Operation::Add => a + b,
-            Operation::Multiply => a * b,
-        }
+    fn eval(&self, a: u64, b: u64) -> Result<u64, Error> {
+        let result = match self {
+            Operation::Add => a.checked_add(b),
+            Operation::Subtract => a.checked_sub(b),
+            Operation::Multiply => a.checked_mul(b),
+            Operation::Square => a.checked_mul(a),
+        };
+
+        result.with_context(|| format!("{:?} of {} and {} is out of range", self, a, b))
     }
 }
 
 fn parse_input(input: &str) -> Result<Vec<Monkey>, Error> {
-    fn parse_items(s: &str) -> IResult<&str, Vec<u64>> {
+    fn parse_items(s: &str) -> IResult<&str, VecDeque<u64>> {
         let map_items = map_res(separated_list1(tag(", "), digit1), |items| {
             items
                 .into_iter()
                 .map(|i: &str| i.parse::<u64>())
-                .collect::<Result<Vec<_>, _>>()
+                .collect::<Result<VecDeque<_>, _>>()
         });
 
         let (s, (_, items)) = tuple((tag("Starting items: "), map_items))(s)?;
@@ -64,6 +112,7 @@ fn parse_input(input: &str) -> Result<Vec<Monkey>, Error> {
 
         let map_op = alt((
             value(Operation::Add, char('+')),
+            value(Operation::Subtract, char('-')),
             value(Operation::Multiply, char('*')),
         ));
 
@@ -72,6 +121,11 @@ fn parse_input(input: &str) -> Result<Vec<Monkey>, Error> {
 
         let (s, _) = multispace0(s)?;
 
+        let operation = match (arg1, &operation, arg2) {
+            (None, Operation::Multiply, None) => Operation::Square,
+            _ => operation,
+        };
+
         Ok((s, (arg1, operation, arg2)))
     }
 
@@ -120,74 +174,112 @@ fn parse_input(input: &str) -> Result<Vec<Monkey>, Error> {
     let (_, monkeys) =
         many1(parse_monkey)(input).map_err(|e| anyhow!("could not parse monkeys: {}", e))?;
 
+    for (i, m) in monkeys.iter().enumerate() {
+        for target in [m.if_true, m.if_false] {
+            if target >= monkeys.len() {
+                bail!(
+                    "monkey {} throws to monkey {}, but there are only {} monkeys",
+                    i,
+                    target,
+                    monkeys.len()
+                );
+            }
+            if target == i {
+                bail!("monkey {} throws to itself", i);
+            }
+        }
+        // Both targets are borrowed at once while simulating
+        if m.if_true == m.if_false {
+            bail!("monkey {} throws to monkey {} either way", i, m.if_true);
+        }
+    }
+
     Ok(monkeys)
 }
 
-fn simulate_rounds(monkeys: Vec<Monkey>, rounds: usize, relief: impl Fn(u64) -> u64) -> Vec<usize> {
+fn simulate_rounds(
+    monkeys: Vec<Monkey>,
+    rounds: usize,
+    relief: impl Fn(u64) -> u64,
+) -> Result<Vec<u64>, Error> {
+    let (inspections, _) = simulate_rounds_tracking_empty(monkeys, rounds, relief)?;
+    Ok(inspections)
+}
+
+// Also returns, per monkey, the first round from which it neither held nor received an item in
+// any remaining round. Rounds count from 1.
+fn simulate_rounds_tracking_empty(
+    monkeys: Vec<Monkey>,
+    rounds: usize,
+    relief: impl Fn(u64) -> u64,
+) -> Result<(Vec<u64>, Vec<Option<usize>>), Error> {
     let monkeys: Vec<_> = monkeys.into_iter().map(RefCell::new).collect();
     let mut inspections = vec![0; monkeys.len()];
+    let mut empty_since = vec![None; monkeys.len()];
 
-    for _ in 0..rounds {
+    for round in 1..=rounds {
+        let mut active = vec![false; monkeys.len()];
         for i in 0..monkeys.len() {
             let mut m = monkeys[i].borrow_mut();
             let mut t = monkeys[m.if_true].borrow_mut();
             let mut f = monkeys[m.if_false].borrow_mut();
 
-            let arg1 = m.arg1;
-            let arg2 = m.arg2;
-            let operation = m.operation.clone();
             let test = m.test;
 
-            inspections[i] += m.items.len();
-            for item in m.items.drain(..) {
-                let a = arg1.unwrap_or(item);
-                let b = arg2.unwrap_or(item);
-                let item = operation.eval(a, b);
-                let item = relief(item);
-
-                if item % test == 0 {
-                    t.items.push(item);
+            inspections[i] += m.items.len() as u64;
+            if !m.items.is_empty() {
+                active[i] = true;
+            }
+            while let Some(item) = m.items.pop_front() {
+                let worry = m
+                    .inspect(item)
+                    .with_context(|| format!("monkey {} inspecting item {}", i, item))?;
+                let item = relief(worry);
+
+                if item.is_multiple_of(test) {
+                    t.items.push_back(item);
+                    active[m.if_true] = true;
                 } else {
-                    f.items.push(item);
+                    f.items.push_back(item);
+                    active[m.if_false] = true;
                 }
             }
         }
-    }
-
-    inspections
-}
 
-pub fn calculate_business(inspections: &[usize]) -> usize {
-    // Weird but efficient way of "find top 2 items"
-    // Redeeming myself after day 1
-    let max2 = inspections.iter().fold((0, 0), |(a, b), &c| {
-        if c > b {
-            if c > a {
-                (c, a)
-            } else {
-                (a, c)
+        for (active, empty_since) in active.into_iter().zip(&mut empty_since) {
+            if active {
+                *empty_since = None;
+            } else if empty_since.is_none() {
+                *empty_since = Some(round);
             }
-        } else {
-            (a, b)
         }
-    });
+    }
+
+    Ok((inspections, empty_since))
+}
 
-    max2.0 * max2.1
+// Product of the `top_k` highest inspection counts
+fn monkey_business(inspections: &[u64], top_k: usize) -> u64 {
+    inspections
+        .iter()
+        .sorted_unstable_by_key(|&&i| Reverse(i))
+        .take(top_k)
+        .product()
 }
 
-fn part1(input: &str) -> Result<usize, Error> {
+fn part1(input: &str) -> Result<u64, Error> {
     let monkeys = parse_input(input)?;
 
-    let inspections = simulate_rounds(monkeys, 20, |worry| worry / 3);
-    Ok(calculate_business(&inspections))
+    let inspections = simulate_rounds(monkeys, 20, |worry| worry / 3)?;
+    Ok(monkey_business(&inspections, 2))
 }
 
-fn part2(input: &str) -> Result<usize, Error> {
+fn part2(input: &str) -> Result<u64, Error> {
     let monkeys = parse_input(input)?;
     let shared_mod: u64 = monkeys.iter().map(|m| m.test).product();
 
-    let inspections = simulate_rounds(monkeys, 10000, |worry| worry % shared_mod);
-    Ok(calculate_business(&inspections))
+    let inspections = simulate_rounds(monkeys, 10000, |worry| worry % shared_mod)?;
+    Ok(monkey_business(&inspections, 2))
 }
 
 fn main() -> Result<(), Error> {
@@ -203,7 +295,87 @@ fn main() -> Result<(), Error> {
 mod tests {
     use rstest::rstest;
 
-    use super::{parse_input, part1, part2, simulate_rounds};
+    use super::{
+        monkey_business, parse_input, part1, part2, simulate_rounds,
+        simulate_rounds_tracking_empty, Operation,
+    };
+
+    #[test]
+    fn square_operation() {
+        let monkeys = parse_input(TEST_INPUT).unwrap();
+        assert_eq!(monkeys[2].operation, Operation::Square);
+        assert_eq!(monkeys[2].inspect(5).unwrap(), 25);
+        assert_eq!(monkeys[0].operation, Operation::Multiply);
+    }
+
+    #[test]
+    fn display_monkey() {
+        let monkeys = parse_input(TEST_INPUT).unwrap();
+        assert_eq!(
+            monkeys[0].to_string(),
+            "Monkey[items=[79,98], op=*19, test=%23 ->2/3]"
+        );
+        assert_eq!(
+            monkeys[2].to_string(),
+            "Monkey[items=[79,60,97], op=*old, test=%13 ->1/3]"
+        );
+
+        let input = TEST_INPUT.replace("new = old * 19", "new = 4 - old");
+        let monkeys = parse_input(&input).unwrap();
+        assert_eq!(
+            monkeys[0].to_string(),
+            "Monkey[items=[79,98], op=4-old, test=%23 ->2/3]"
+        );
+    }
+
+    #[test]
+    fn subtract_underflow() {
+        let input = TEST_INPUT.replace("new = old * 19", "new = old - 100");
+        let monkeys = parse_input(&input).unwrap();
+        assert_eq!(monkeys[0].operation, Operation::Subtract);
+        assert_eq!(monkeys[0].inspect(150).unwrap(), 50);
+
+        let err = simulate_rounds(monkeys, 1, |worry| worry / 3).unwrap_err();
+        assert_eq!(err.to_string(), "monkey 0 inspecting item 79");
+    }
+
+    #[test]
+    fn permanently_empty() {
+        let monkeys = parse_input(TEST_INPUT).unwrap();
+        let (inspections, empty_since) =
+            simulate_rounds_tracking_empty(monkeys, 20, |worry| worry / 3).unwrap();
+        // Monkeys 2 and 3 end every round empty handed, but that alone doesn't make them idle. Only
+        // monkey 2 goes quiet for good, it inspects just 7 items in all 20 rounds.
+        assert_eq!(inspections, [101, 95, 7, 105]);
+        assert_eq!(empty_since, [None, None, Some(17), None]);
+
+        // Everything is divisible by 1, so monkey 0 hands its items to 1 in the first round, and from
+        // then on they only go from 1 to 2 and back
+        let input = "Monkey 0:
+  Starting items: 1, 2
+  Operation: new = old + 0
+  Test: divisible by 1
+    If true: throw to monkey 1
+    If false: throw to monkey 2
+
+Monkey 1:
+  Starting items: 3
+  Operation: new = old + 0
+  Test: divisible by 1
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 4
+  Operation: new = old + 0
+  Test: divisible by 1
+    If true: throw to monkey 1
+    If false: throw to monkey 0
+";
+        let monkeys = parse_input(input).unwrap();
+        let (_, empty_since) = simulate_rounds_tracking_empty(monkeys, 5, |worry| worry).unwrap();
+        assert_eq!(empty_since, [Some(2), None, None]);
+    }
 
     #[test]
     fn part1_example() {
@@ -228,14 +400,45 @@ mod tests {
     #[case(8000, [41728, 38268, 1553, 41606])]
     #[case(9000, [46945, 43051, 1746, 46807])]
     #[case(10000, [52166, 47830, 1938, 52013])]
-    fn part2_inspection_examples(#[case] rounds: usize, #[case] expected_inspections: [usize; 4]) {
+    fn part2_inspection_examples(#[case] rounds: usize, #[case] expected_inspections: [u64; 4]) {
         let monkeys = parse_input(TEST_INPUT).unwrap();
         let shared_mod: u64 = monkeys.iter().map(|m| m.test).product();
-        let inspections = simulate_rounds(monkeys, rounds, |worry| worry % shared_mod);
+        let inspections = simulate_rounds(monkeys, rounds, |worry| worry % shared_mod).unwrap();
 
         assert_eq!(inspections, expected_inspections);
     }
 
+    #[test]
+    fn invalid_throw_target() {
+        let input = TEST_INPUT.replace("If true: throw to monkey 1", "If true: throw to monkey 99");
+        let err = parse_input(&input).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "monkey 2 throws to monkey 99, but there are only 4 monkeys"
+        );
+
+        let input = TEST_INPUT.replace("If true: throw to monkey 1", "If true: throw to monkey 2");
+        assert!(parse_input(&input).is_err());
+
+        let input = TEST_INPUT.replacen(
+            "If false: throw to monkey 3",
+            "If false: throw to monkey 2",
+            1,
+        );
+        let err = parse_input(&input).err().unwrap();
+        assert_eq!(err.to_string(), "monkey 0 throws to monkey 2 either way");
+    }
+
+    #[test]
+    fn monkey_business_top_k() {
+        assert_eq!(monkey_business(&[1, 2, 3, 4], 3), 24);
+        assert_eq!(monkey_business(&[1, 2, 3, 4], 2), 12);
+
+        let monkeys = parse_input(TEST_INPUT).unwrap();
+        let inspections = simulate_rounds(monkeys, 20, |worry| worry / 3).unwrap();
+        assert_eq!(monkey_business(&inspections, 3), 105 * 101 * 95);
+    }
+
     static TEST_INPUT: &str = "Monkey 0:
   Starting items: 79, 98
   Operation: new = old * 19
@@ -1,8 +1,9 @@
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
+use itertools::{iproduct, Itertools};
 use ndarray::Array2;
 use std::{
-    collections::{HashSet, VecDeque},
-    fs,
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    fs, iter,
     str::FromStr,
 };
 
@@ -14,6 +15,57 @@ struct Map {
 }
 
 impl Map {
+    // Heights are indexed by (x, y)
+    fn new(
+        heights: Array2<u64>,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Result<Self, Error> {
+        for (name, pos) in [("start", start), ("end", end)] {
+            if heights.get(pos).is_none() {
+                bail!("{} {:?} is outside the map", name, pos);
+            }
+        }
+
+        Ok(Map {
+            heights,
+            start,
+            end,
+        })
+    }
+
+    // One line per row of comma-separated heights, with start and end given as (x, y) separately
+    #[cfg(test)]
+    fn from_height_csv(
+        input: &str,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Result<Self, Error> {
+        let rows = input
+            .lines()
+            .enumerate()
+            .map(|(y, line)| {
+                line.split(',')
+                    .map(|h| h.trim().parse::<u64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .with_context(|| format!("invalid height on line {}", y + 1))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let x = rows.first().context("input contains no lines")?.len();
+        if let Some(y) = rows.iter().position(|row| row.len() != x) {
+            bail!(
+                "line {} has {} heights, expected {}",
+                y + 1,
+                rows[y].len(),
+                x
+            );
+        }
+
+        let heights = Array2::from_shape_fn((x, rows.len()), |(x, y)| rows[y][x]);
+        Map::new(heights, start, end)
+    }
+
     fn neighbors(
         &self,
         (x, y): (usize, usize),
@@ -47,11 +99,20 @@ fn bfs(
     start: (usize, usize),
     check_goal: impl Fn((usize, usize)) -> bool,
     reachability: impl Fn(u64, u64) -> bool,
+) -> Option<u64> {
+    bfs_multi_source(map, iter::once(start), check_goal, reachability)
+}
+
+fn bfs_multi_source(
+    map: &Map,
+    starts: impl Iterator<Item = (usize, usize)>,
+    check_goal: impl Fn((usize, usize)) -> bool,
+    reachability: impl Fn(u64, u64) -> bool,
 ) -> Option<u64> {
     let mut to_explore = VecDeque::with_capacity(map.heights.len());
     let mut visited = HashSet::new();
 
-    to_explore.push_back((0, start));
+    to_explore.extend(starts.map(|start| (0, start)));
 
     while let Some((d, node)) = to_explore.pop_front() {
         if check_goal(node) {
@@ -70,22 +131,108 @@ fn bfs(
     None
 }
 
-fn part1(input: &str) -> Result<u64, Error> {
-    let map: Map = input.parse()?;
+// Expands whole layers from both ends, always the smaller frontier. bwd_reach must describe the
+// same moves as fwd_reach but walked backwards, otherwise we fall back to a plain BFS.
+fn bidirectional_bfs(
+    map: &Map,
+    start: (usize, usize),
+    goal: (usize, usize),
+    fwd_reach: impl Fn(u64, u64) -> bool,
+    bwd_reach: impl Fn(u64, u64) -> bool,
+) -> Option<u64> {
+    let heights = map.heights.iter().copied().unique().collect_vec();
+    let inverse = iproduct!(&heights, &heights).all(|(&h, &n)| fwd_reach(h, n) == bwd_reach(n, h));
+    if !inverse {
+        return bfs(map, start, |node| node == goal, fwd_reach);
+    }
+    if start == goal {
+        return Some(0);
+    }
+
+    let mut dist_fwd = HashMap::from([(start, 0)]);
+    let mut dist_bwd = HashMap::from([(goal, 0)]);
+    let mut frontier_fwd = vec![start];
+    let mut frontier_bwd = vec![goal];
+
+    while !frontier_fwd.is_empty() && !frontier_bwd.is_empty() {
+        let (frontier, dist, other_dist, reach): (_, _, _, &dyn Fn(u64, u64) -> bool) =
+            if frontier_fwd.len() <= frontier_bwd.len() {
+                (&mut frontier_fwd, &mut dist_fwd, &dist_bwd, &fwd_reach)
+            } else {
+                (&mut frontier_bwd, &mut dist_bwd, &dist_fwd, &bwd_reach)
+            };
+
+        // Finish the whole layer before answering, a later node in it may meet the other side
+        // through a shorter path
+        let mut best = None;
+        let mut next = Vec::new();
+        for node in frontier.drain(..) {
+            let d = dist[&node];
+            for neighbor in map.neighbors(node, reach) {
+                if let Some(other_d) = other_dist.get(&neighbor) {
+                    best = Some(best.map_or(d + 1 + other_d, |b: u64| b.min(d + 1 + other_d)));
+                }
+                if let Entry::Vacant(e) = dist.entry(neighbor) {
+                    e.insert(d + 1);
+                    next.push(neighbor);
+                }
+            }
+        }
+        if best.is_some() {
+            return best;
+        }
+        *frontier = next;
+    }
 
-    bfs(&map, map.start, |node| node == map.end, |h, n| n <= h + 1).context("could not find end")
+    None
 }
 
-fn part2(input: &str) -> Result<u64, Error> {
+// Size of the region reachable from start, start included
+#[cfg(test)]
+fn reachable_count(
+    map: &Map,
+    start: (usize, usize),
+    reachability: impl Fn(u64, u64) -> bool,
+) -> usize {
+    let mut visited = HashSet::new();
+    let mut stack = vec![start];
+    visited.insert(start);
+
+    while let Some(node) = stack.pop() {
+        for neighbor in map.neighbors(node, &reachability) {
+            if visited.insert(neighbor) {
+                stack.push(neighbor);
+            }
+        }
+    }
+
+    visited.len()
+}
+
+fn part1(input: &str) -> Result<u64, Error> {
     let map: Map = input.parse()?;
 
-    bfs(
+    bidirectional_bfs(
         &map,
+        map.start,
         map.end,
-        |node| map.heights[node] == 0,
+        |h, n| n <= h + 1,
         |h, n| h <= n + 1,
     )
-    .context("could not find any 'a'")
+    .context("could not find end")
+}
+
+fn part2(input: &str) -> Result<u64, Error> {
+    let map: Map = input.parse()?;
+
+    let lowest = map
+        .heights
+        .indexed_iter()
+        .filter(|(_, &h)| h == 0)
+        .map(|(node, _)| node);
+
+    bfs_multi_source(&map, lowest, |node| node == map.end, |h, n| n <= h + 1)
+        .context("could not reach end from any 'a'")
 }
 
 fn main() -> Result<(), Error> {
@@ -98,8 +245,11 @@ fn main() -> Result<(), Error> {
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
-    use super::{part1, part2};
+    use ndarray::array;
+
+    use super::{bfs, bfs_multi_source, bidirectional_bfs, part1, part2, reachable_count, Map};
 
     #[test]
     fn part1_example() {
@@ -111,6 +261,88 @@ mod tests {
         assert_eq!(part2(TEST_INPUT).unwrap(), 29);
     }
 
+    #[test]
+    fn bidirectional_example() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+        let fwd = |h: u64, n: u64| n <= h + 1;
+        let bwd = |h: u64, n: u64| h <= n + 1;
+
+        let plain = bfs(&map, map.start, |n| n == map.end, fwd);
+        assert_eq!(plain, Some(31));
+        assert_eq!(bidirectional_bfs(&map, map.start, map.end, fwd, bwd), plain);
+        assert_eq!(
+            bidirectional_bfs(&map, map.end, map.start, bwd, fwd),
+            Some(31)
+        );
+        assert_eq!(
+            bidirectional_bfs(&map, map.start, map.start, fwd, bwd),
+            Some(0)
+        );
+
+        // Not inverses, falls back to plain BFS
+        assert_eq!(bidirectional_bfs(&map, map.start, map.end, fwd, fwd), plain);
+    }
+
+    #[test]
+    fn multi_source_example() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+        let starts = [map.start, (0, 4), (0, 1)];
+
+        let d = bfs_multi_source(
+            &map,
+            starts.into_iter(),
+            |n| n == map.end,
+            |h, n| n <= h + 1,
+        );
+        assert_eq!(d, Some(29));
+    }
+
+    #[test]
+    fn reachable_region() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+        let count = reachable_count(&map, map.start, |h, n| n <= h + 1);
+        assert_eq!(count, map.heights.len());
+    }
+
+    #[test]
+    fn constructed_map() {
+        let heights = array![[0, 1, 2], [1, 5, 3], [2, 3, 4]];
+        let map = Map::new(heights, (0, 0), (2, 2)).unwrap();
+
+        let d = bfs(&map, map.start, |n| n == map.end, |h, n| n <= h + 1);
+        assert_eq!(d, Some(4));
+
+        let d = bfs(&map, map.start, |n| n == (1, 1), |h, n| n <= h + 1);
+        assert_eq!(d, None);
+
+        assert!(Map::new(array![[0]], (0, 0), (0, 1)).is_err());
+    }
+
+    #[test]
+    fn invalid_height() {
+        let err = "Sab\na#E\n".parse::<Map>().unwrap_err();
+        assert_eq!(err.to_string(), "invalid height '#' at (1, 1)");
+    }
+
+    #[test]
+    fn height_csv() {
+        let csv = "0, 1, 2
+1, 5, 3
+2, 3, 4
+";
+        let map = Map::from_height_csv(csv, (0, 0), (2, 2)).unwrap();
+        assert_eq!(map.heights[(2, 1)], 3);
+        assert_eq!(map.heights[(1, 2)], 3);
+
+        let d = bfs(&map, map.start, |n| n == map.end, |h, n| n <= h + 1);
+        assert_eq!(d, Some(4));
+
+        let err = Map::from_height_csv("0,1\n2\n", (0, 0), (1, 0)).unwrap_err();
+        assert_eq!(err.to_string(), "line 2 has 1 heights, expected 2");
+        assert!(Map::from_height_csv("0,x\n", (0, 0), (0, 0)).is_err());
+        assert!(Map::from_height_csv("0,1\n", (0, 0), (0, 1)).is_err());
+    }
+
     static TEST_INPUT: &str = "Sabqponm
 abcryxxl
 accszExk
@@ -141,15 +373,19 @@ impl FromStr for Map {
                     c = 'z';
                 }
 
+                if !c.is_ascii_lowercase() {
+                    bail!("invalid height '{}' at ({}, {})", c, x, y);
+                }
+
                 let h = c as u64 - ('a' as u64);
                 heights[(x, y)] = h;
             }
         }
 
-        Ok(Map {
+        Map::new(
             heights,
-            start: start.context("map contained no start")?,
-            end: end.context("map contained no end")?,
-        })
+            start.context("map contained no start")?,
+            end.context("map contained no end")?,
+        )
     }
 }
@@ -1,8 +1,12 @@
-use anyhow::{bail, Error};
+use anyhow::{bail, Context, Error};
 use itertools::Itertools;
 use nom::{
-    branch::alt, bytes::complete::tag, character::complete::digit0, combinator::map_res,
-    multi::separated_list0, IResult,
+    branch::alt,
+    bytes::complete::tag,
+    character::complete::digit1,
+    combinator::{all_consuming, map_res},
+    multi::separated_list0,
+    IResult,
 };
 use std::{cmp::Ordering, fmt::Debug, fs, str::FromStr};
 
@@ -22,6 +26,18 @@ impl Packet {
     }
 }
 
+impl From<u64> for Packet {
+    fn from(value: u64) -> Self {
+        Packet::Literal(value)
+    }
+}
+
+impl From<Vec<Packet>> for Packet {
+    fn from(items: Vec<Packet>) -> Self {
+        Packet::List(items)
+    }
+}
+
 impl PartialOrd for Packet {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -38,9 +54,41 @@ impl Ord for Packet {
     }
 }
 
+// Same as Ord::cmp, but also describes the comparison that decided the result
+#[cfg(test)]
+fn compare_explain(a: &Packet, b: &Packet) -> (Ordering, String) {
+    if let (Packet::Literal(left), Packet::Literal(right)) = (a, b) {
+        let ordering = left.cmp(right);
+        return (
+            ordering,
+            format!("left {} vs right {} -> {:?}", left, right, ordering),
+        );
+    }
+
+    let (left, right) = (a.as_slice(), b.as_slice());
+    for (l, r) in left.iter().zip(right) {
+        let (ordering, trace) = compare_explain(l, r);
+        if ordering != Ordering::Equal {
+            return (ordering, trace);
+        }
+    }
+
+    let ordering = left.len().cmp(&right.len());
+    (
+        ordering,
+        format!(
+            "left length {} vs right length {} -> {:?}",
+            left.len(),
+            right.len(),
+            ordering
+        ),
+    )
+}
+
 fn get_packets(input: &str) -> Result<Vec<Packet>, Error> {
     let packets: Vec<Packet> = input
         .lines()
+        .map(str::trim)
         .filter(|l| !l.is_empty())
         .map(|l| l.parse())
         .collect::<Result<Vec<_>, _>>()?;
@@ -60,17 +108,42 @@ fn part1(input: &str) -> Result<usize, Error> {
         .sum())
 }
 
-fn part2(input: &str) -> Result<usize, Error> {
+#[cfg(test)]
+fn first_unordered_pair(input: &str) -> Result<Option<usize>, Error> {
     let packets = get_packets(input)?;
 
-    let divider_1 = "[[2]]".parse()?;
-    let divider_2 = "[[6]]".parse()?;
+    Ok(packets
+        .iter()
+        .tuples()
+        .position(|(a, b)| a > b)
+        .map(|i| i + 1))
+}
+
+const DIVIDERS: [&str; 2] = ["[[2]]", "[[6]]"];
+
+fn sorted_with_dividers(input: &str) -> Result<Vec<String>, Error> {
+    let mut packets = get_packets(input)?;
+    for d in DIVIDERS {
+        packets.push(d.parse()?);
+    }
+    packets.sort_unstable();
+
+    Ok(packets.iter().map(|p| format!("{:?}", p)).collect())
+}
+
+fn part2(input: &str) -> Result<usize, Error> {
+    let sorted = sorted_with_dividers(input)?;
 
-    // We don't have to sort to find the positions of these :)
-    let d1_pos = packets.iter().filter(|p| p < &&divider_1).count() + 1;
-    let d2_pos = packets.iter().filter(|p| p < &&divider_2).count() + 2;
+    let mut key = 1;
+    for d in DIVIDERS {
+        let pos = sorted
+            .iter()
+            .position(|p| p == d)
+            .with_context(|| format!("divider {} missing from sorted packets", d))?;
+        key *= pos + 1;
+    }
 
-    Ok(d1_pos * d2_pos)
+    Ok(key)
 }
 
 fn main() -> Result<(), Error> {
@@ -83,8 +156,15 @@ fn main() -> Result<(), Error> {
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
-    use super::{get_packets, part1, part2};
+    use std::cmp::Ordering;
+
+    use super::{
+        compare_explain, first_unordered_pair, get_packets, part1, part2, sorted_with_dividers,
+        Packet,
+    };
+    use rstest::rstest;
 
     #[test]
     fn part1_example() {
@@ -97,21 +177,88 @@ mod tests {
     }
 
     #[test]
-    fn packet_sorting() {
-        let mut packets = get_packets(TEST_INPUT).unwrap();
-        let divider_1 = "[[2]]".parse().unwrap();
-        let divider_2 = "[[6]]".parse().unwrap();
-        packets.push(divider_1);
-        packets.push(divider_2);
-        packets.sort_unstable();
-
-        let output = packets
-            .iter()
-            .map(|p| format!("{:?}", p))
-            .collect::<Vec<String>>()
-            .join("\n");
+    fn first_unordered_pair_example() {
+        assert_eq!(first_unordered_pair(TEST_INPUT).unwrap(), Some(3));
+        assert_eq!(first_unordered_pair("[1]\n[2]\n").unwrap(), None);
+    }
+
+    #[rstest]
+    #[case("[9]", "[[8,7,6]]", Ordering::Greater, "left 9 vs right 8 -> Greater")]
+    #[case(
+        "[[4,4],4,4]",
+        "[[4,4],4,4,4]",
+        Ordering::Less,
+        "left length 3 vs right length 4 -> Less"
+    )]
+    #[case(
+        "[[1],[2,3,4]]",
+        "[[1],4]",
+        Ordering::Less,
+        "left 2 vs right 4 -> Less"
+    )]
+    fn compare_explanations(
+        #[case] a: &str,
+        #[case] b: &str,
+        #[case] expected: Ordering,
+        #[case] expected_trace: &str,
+    ) {
+        let a: Packet = a.parse().unwrap();
+        let b: Packet = b.parse().unwrap();
+        assert_eq!(
+            compare_explain(&a, &b),
+            (expected, expected_trace.to_string())
+        );
+        assert_eq!(a.cmp(&b), expected);
+    }
+
+    #[test]
+    fn whitespace_separator_lines() {
+        let input = TEST_INPUT
+            .replace("\n\n", "\n   \n")
+            .replace("[9]", "  [9]\t");
+        assert_eq!(
+            get_packets(&input).unwrap(),
+            get_packets(TEST_INPUT).unwrap()
+        );
+        assert_eq!(part1(&input).unwrap(), 13);
+    }
+
+    #[test]
+    fn ergonomic_constructors() {
+        let divider = Packet::from(vec![vec![2.into()].into()]);
+        assert_eq!(
+            divider,
+            Packet::List(vec![Packet::List(vec![Packet::Literal(2)])])
+        );
+        assert_eq!(divider, "[[2]]".parse().unwrap());
+    }
+
+    #[test]
+    fn parse_empty_lists() {
+        use Packet::List;
+
+        assert_eq!("[]".parse::<Packet>().unwrap(), List(vec![]));
+        assert_eq!("[[]]".parse::<Packet>().unwrap(), List(vec![List(vec![])]));
+        assert_eq!(
+            "[[],[]]".parse::<Packet>().unwrap(),
+            List(vec![List(vec![]), List(vec![])])
+        );
+    }
+
+    #[rstest]
+    #[case("[,]")]
+    #[case("[1,,2]")]
+    #[case("[1,]")]
+    #[case("[1]]")]
+    #[case("")]
+    fn parse_malformed(#[case] input: &str) {
+        assert!(input.parse::<Packet>().is_err());
+    }
 
-        assert_eq!(output, EXPECTED_SORTED)
+    #[test]
+    fn packet_sorting() {
+        let sorted = sorted_with_dividers(TEST_INPUT).unwrap();
+        assert_eq!(sorted.join("\n"), EXPECTED_SORTED);
     }
 
     static TEST_INPUT: &str = "[1,1,3,1,1]
@@ -184,7 +331,8 @@ impl FromStr for Packet {
         }
 
         fn parse_literal_packet(s: &str) -> IResult<&str, Packet> {
-            let (s, literal) = map_res(digit0, |s: &str| s.parse())(s)?;
+            // digit1 so an empty element like the one in "[1,,2]" is an error, not a zero-width literal
+            let (s, literal) = map_res(digit1, |s: &str| s.parse())(s)?;
 
             Ok((s, Packet::Literal(literal)))
         }
@@ -193,7 +341,7 @@ impl FromStr for Packet {
             alt((parse_list_packet, parse_literal_packet))(s)
         }
 
-        match parse_packet(s) {
+        match all_consuming(parse_packet)(s) {
             Ok((_, p)) => Ok(p),
             Err(error) => bail!("{}", error),
         }
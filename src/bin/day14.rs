@@ -1,20 +1,154 @@
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
 use itertools::Itertools;
+#[cfg(test)]
+use ndarray::Array2;
 use std::{collections::HashSet, fs, str::FromStr};
 
+// Which cells a grain of sand may try to fall into, in order of preference
+#[derive(Debug, Default, Clone, Copy)]
+enum SlideRule {
+    // Down, then down-left, then down-right
+    #[default]
+    Diagonal,
+    // Straight down only
+    #[cfg(test)]
+    Straight,
+}
+
+impl SlideRule {
+    // Returned in reverse order of preference, as they are pushed onto a stack
+    fn candidates(self, (x, y): (usize, usize)) -> impl DoubleEndedIterator<Item = (usize, usize)> {
+        let xs = match self {
+            SlideRule::Diagonal => [Some(x + 1), x.checked_sub(1), Some(x)],
+            #[cfg(test)]
+            SlideRule::Straight => [None, None, Some(x)],
+        };
+        xs.into_iter().flatten().map(move |x| (x, y + 1))
+    }
+}
+
+// Far beyond any real input, but keeps a typo from filling memory with rocks
+const MAX_COORD: usize = 100_000;
+
+#[cfg(test)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Space {
+    Air,
+    Rock,
+    Sand,
+}
+
 struct Map {
     rocks: HashSet<(usize, usize)>,
     bottom: usize,
 }
 
 impl Map {
-    fn fill_sand(&self, start: (usize, usize), has_floor: bool) -> HashSet<(usize, usize)> {
+    // Returns the settled sand and how many grains fell into the abyss. That is at most one, as
+    // every grain after the first to fall would follow it down.
+    fn fill_sand(
+        &self,
+        start: (usize, usize),
+        has_floor: bool,
+        rule: SlideRule,
+    ) -> (HashSet<(usize, usize)>, usize) {
+        let mut sand = HashSet::new();
+        let fell = self.pour(start, has_floor, rule, &mut sand);
+        (sand, fell as usize)
+    }
+
+    // Pours from each source in turn, until it is blocked or sand starts falling into the abyss
+    #[cfg(test)]
+    fn fill_sand_sources(
+        &self,
+        sources: &[(usize, usize)],
+        has_floor: bool,
+    ) -> HashSet<(usize, usize)> {
+        let mut sand = HashSet::new();
+        for &source in sources {
+            if self.rocks.contains(&source) || sand.contains(&source) {
+                continue;
+            }
+            if self.pour(source, has_floor, SlideRule::default(), &mut sand) {
+                break;
+            }
+        }
+        sand
+    }
+
+    // Drops grains one at a time, counting per (x, y) cell how many settled grains passed through it,
+    // their resting cell included
+    #[cfg(test)]
+    fn fill_sand_counting(&self, start: (usize, usize), has_floor: bool) -> Array2<u32> {
+        // With a floor, the pile can spread at most one cell sideways per row
+        let max_x = self.rocks.iter().map(|&(x, _)| x).max().unwrap_or(0);
+        let mut counts = Array2::zeros((max_x.max(start.0) + self.bottom + 1, self.bottom));
+        let mut sand = HashSet::new();
+
+        'grains: while !sand.contains(&start) {
+            let mut path = vec![start];
+            loop {
+                let p = *path.last().unwrap();
+                let next = SlideRule::default().candidates(p).rev().find(|n| {
+                    !(self.rocks.contains(n) || sand.contains(n) || has_floor && n.1 >= self.bottom)
+                });
+
+                match next {
+                    Some((_, y)) if y >= self.bottom => break 'grains,
+                    Some(n) => path.push(n),
+                    None => {
+                        sand.insert(p);
+                        break;
+                    }
+                }
+            }
+
+            for p in path {
+                counts[p] += 1;
+            }
+        }
+
+        counts
+    }
+
+    // Every non-air cell, given the sand settled on this map, in no particular order
+    #[cfg(test)]
+    fn occupied<'a>(
+        &'a self,
+        sand: &'a HashSet<(usize, usize)>,
+    ) -> impl Iterator<Item = ((usize, usize), Space)> + 'a {
+        let rocks = self.rocks.iter().map(|&p| (p, Space::Rock));
+        let sand = sand.iter().map(|&p| (p, Space::Sand));
+        rocks.chain(sand)
+    }
+
+    // What is at (x, y) given the sand settled on this map, or None below the floor
+    #[cfg(test)]
+    fn at(&self, sand: &HashSet<(usize, usize)>, x: usize, y: usize) -> Option<Space> {
+        if x > MAX_COORD || y >= self.bottom {
+            None
+        } else if self.rocks.contains(&(x, y)) {
+            Some(Space::Rock)
+        } else if sand.contains(&(x, y)) {
+            Some(Space::Sand)
+        } else {
+            Some(Space::Air)
+        }
+    }
+
+    // Returns true if sand fell into the abyss
+    fn pour(
+        &self,
+        start: (usize, usize),
+        has_floor: bool,
+        rule: SlideRule,
+        sand: &mut HashSet<(usize, usize)>,
+    ) -> bool {
         enum Explore {
             Open((usize, usize)),
             Close((usize, usize)),
         }
 
-        let mut sand = HashSet::new();
         let mut explore_stack = vec![Explore::Open(start)];
 
         while let Some(e) = explore_stack.pop() {
@@ -24,11 +158,11 @@ impl Map {
                         if has_floor {
                             continue;
                         }
-                        break;
+                        return true;
                     }
 
                     explore_stack.push(Explore::Close((x, y)));
-                    for n in [(x + 1, y + 1), (x - 1, y + 1), (x, y + 1)] {
+                    for n in rule.candidates((x, y)) {
                         if !(self.rocks.contains(&n) || sand.contains(&n)) {
                             explore_stack.push(Explore::Open(n));
                         }
@@ -40,20 +174,20 @@ impl Map {
             }
         }
 
-        sand
+        false
     }
 }
 
 fn part1(input: &str) -> Result<usize, Error> {
     let map: Map = input.parse()?;
-    let sand = map.fill_sand((500, 0), false);
+    let (sand, _) = map.fill_sand((500, 0), false, SlideRule::default());
 
     Ok(sand.len())
 }
 
 fn part2(input: &str) -> Result<usize, Error> {
     let map: Map = input.parse()?;
-    let sand = map.fill_sand((500, 0), true);
+    let (sand, _) = map.fill_sand((500, 0), true, SlideRule::default());
 
     Ok(sand.len())
 }
@@ -68,8 +202,9 @@ fn main() -> Result<(), Error> {
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
-    use super::{part1, part2};
+    use super::{part1, part2, Map, SlideRule, Space};
 
     #[test]
     fn part1_example() {
@@ -80,6 +215,99 @@ mod tests {
     fn part2_example() {
         assert_eq!(part2(TEST_INPUT).unwrap(), 93);
     }
+
+    #[test]
+    fn lost_grains() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+
+        let (sand, lost) = map.fill_sand((500, 0), false, SlideRule::default());
+        assert_eq!((sand.len(), lost), (24, 1));
+
+        let (sand, lost) = map.fill_sand((500, 0), true, SlideRule::default());
+        assert_eq!((sand.len(), lost), (93, 0));
+    }
+
+    #[test]
+    fn sand_heatmap() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+
+        let counts = map.fill_sand_counting((500, 0), false);
+        assert_eq!(counts[(500, 0)], 24);
+        // The first grain rests here, and nothing passes through settled sand
+        assert_eq!(counts[(500, 8)], 1);
+
+        let counts = map.fill_sand_counting((500, 0), true);
+        assert_eq!(counts[(500, 0)], 93);
+    }
+
+    #[test]
+    fn occupied_cells() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+        let (sand, _) = map.fill_sand((500, 0), false, SlideRule::default());
+
+        let (rocks, sand): (Vec<_>, Vec<_>) = map
+            .occupied(&sand)
+            .partition(|(_, space)| *space == Space::Rock);
+        assert_eq!(rocks.len(), 20);
+        assert_eq!(sand.len(), 24);
+    }
+
+    #[test]
+    fn cell_at() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+        let (sand, _) = map.fill_sand((500, 0), false, SlideRule::default());
+
+        assert_eq!(map.at(&sand, 500, 8), Some(Space::Sand));
+        assert_eq!(map.at(&sand, 502, 9), Some(Space::Rock));
+        assert_eq!(map.at(&sand, 500, 0), Some(Space::Air));
+        assert_eq!(map.at(&sand, 10, 3), Some(Space::Air));
+        assert_eq!(map.at(&sand, 500, 11), None);
+        assert_eq!(map.at(&sand, usize::MAX, 0), None);
+    }
+
+    #[test]
+    fn slide_rules() {
+        let map: Map = TEST_INPUT.parse().unwrap();
+
+        let (diagonal, _) = map.fill_sand((500, 0), false, SlideRule::Diagonal);
+        let (straight, _) = map.fill_sand((500, 0), false, SlideRule::Straight);
+
+        assert_eq!(diagonal.len(), 24);
+        assert_eq!(straight.len(), 9);
+    }
+
+    #[test]
+    fn multiple_sources() {
+        let map: Map = "10,1 -> 10,3 -> 14,3 -> 14,1\n20,1 -> 20,3 -> 24,3 -> 24,1\n"
+            .parse()
+            .unwrap();
+
+        let sand = map.fill_sand_sources(&[(12, 0), (22, 0)], false);
+        assert_eq!(sand.len(), 14);
+
+        // Pouring stops entirely once sand reaches the abyss
+        let sand = map.fill_sand_sources(&[(12, 0), (30, 0), (22, 0)], false);
+        assert_eq!(sand.len(), 7);
+    }
+
+    #[test]
+    fn out_of_bounds_rock() {
+        let err = "498,4 -> 498,6\n5000000,9 -> 494,9\n"
+            .parse::<Map>()
+            .err()
+            .unwrap();
+        assert_eq!(err.to_string(), "rock at (5000000, 9) is out of bounds");
+
+        let err = "498,4 -> 498,6\n99999999999999999999999,9 -> 494,9\n"
+            .parse::<Map>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "invalid x coordinate in '99999999999999999999999,9'"
+        );
+    }
+
     static TEST_INPUT: &str = "498,4 -> 498,6 -> 496,6
 503,4 -> 502,4 -> 502,9 -> 494,9
 ";
@@ -97,8 +325,15 @@ impl FromStr for Map {
                 let (x, y) = part
                     .split_once(',')
                     .context("could not split into coords")?;
-                let x = x.parse::<usize>()?;
-                let y = y.parse::<usize>()?;
+                let x = x
+                    .parse::<usize>()
+                    .with_context(|| format!("invalid x coordinate in '{}'", part))?;
+                let y = y
+                    .parse::<usize>()
+                    .with_context(|| format!("invalid y coordinate in '{}'", part))?;
+                if x > MAX_COORD || y > MAX_COORD {
+                    bail!("rock at ({}, {}) is out of bounds", x, y);
+                }
                 line_parts.push((x, y));
             }
             lines.push(line_parts);
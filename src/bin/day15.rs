@@ -1,9 +1,10 @@
-use anyhow::{bail, Context, Error};
+use anyhow::{Context, Error};
+use itertools::Itertools;
 use lazy_static::lazy_static;
 use regex::Regex;
 use std::{collections::HashSet, fs, ops::Range, str::FromStr};
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 struct Position {
     x: i64,
     y: i64,
@@ -26,6 +27,7 @@ impl Position {
 #[derive(Debug)]
 struct Sensor {
     position: Position,
+    beacon: Position,
     distance: u64,
 }
 
@@ -42,6 +44,28 @@ impl Sensor {
         }
     }
 
+    // Whether every position this sensor covers is also covered by `self`
+    fn contains(&self, other: &Self) -> bool {
+        self.position.distance(&other.position) + other.distance <= self.distance
+    }
+
+    // The ring of positions just outside the detection box, clipped to bounds
+    #[cfg(test)]
+    fn boundary_points(&self, bounds: u64) -> impl Iterator<Item = Position> + '_ {
+        let r = self.distance as i64 + 1;
+        (-r..=r)
+            .flat_map(move |dy| {
+                let dx = r - dy.abs();
+                let y = self.position.y + dy;
+                // The top and bottom corners only have one position
+                [self.position.x - dx, self.position.x + dx]
+                    .into_iter()
+                    .take(if dx == 0 { 1 } else { 2 })
+                    .map(move |x| Position::new(x, y))
+            })
+            .filter(move |p| p.in_bounds(bounds))
+    }
+
     // Coefficients for line equations of just outside the detection box
     // y =  x + a
     // y = -x + b
@@ -77,55 +101,116 @@ impl FromStr for Sensor {
         let beacon = Position::new(captures[3].parse()?, captures[4].parse()?);
         let distance = position.distance(&beacon);
 
-        Ok(Sensor { position, distance })
+        Ok(Sensor {
+            position,
+            beacon,
+            distance,
+        })
     }
 }
 
-fn part1(input: &str, y: i64) -> Result<usize, Error> {
-    let sensors = input
-        .lines()
-        .map(|l| l.parse::<Sensor>())
-        .collect::<Result<Vec<_>, _>>()?;
+// Flags sensors whose whole range is covered by another sensor. Of two identical
+// sensors, the first one is kept
+fn dominated(sensors: &[Sensor]) -> Vec<bool> {
+    sensors
+        .iter()
+        .enumerate()
+        .map(|(i, s)| {
+            sensors
+                .iter()
+                .enumerate()
+                .any(|(j, other)| i != j && other.contains(s) && (j < i || !s.contains(other)))
+        })
+        .collect()
+}
+
+fn drop_dominated(sensors: Vec<Sensor>) -> Vec<Sensor> {
+    let dominated = dominated(&sensors);
+    sensors
+        .into_iter()
+        .zip(dominated)
+        .filter_map(|(s, d)| (!d).then_some(s))
+        .collect()
+}
+
+// (kept, dropped)
+#[cfg(test)]
+fn coverage_stats(sensors: &[Sensor]) -> (usize, usize) {
+    let dropped = dominated(sensors).into_iter().filter(|d| *d).count();
+    (sensors.len() - dropped, dropped)
+}
 
-    let mut ranges = Vec::new();
-    for mut r in sensors.iter().filter_map(|s| s.x_span_at_y(y)) {
-        ranges.retain(|c: &Range<i64>| {
-            let max_start = std::cmp::max(r.start, c.start);
-            let min_end = std::cmp::min(r.end, r.end);
-
-            if max_start <= min_end {
-                let start = std::cmp::min(r.start, c.start);
-                let end = std::cmp::max(r.end, c.end);
-                r = start..end;
-                false
-            } else {
-                true
+// Positions in the given rows that cannot contain a beacon, i.e. covered by some sensor and not
+// already a known beacon
+fn coverage_in_band(sensors: &[Sensor], y_range: Range<i64>) -> u64 {
+    let mut count = 0;
+    for y in y_range {
+        // x_span_at_y's end is the last covered x, not one past it
+        let spans = sensors
+            .iter()
+            .filter_map(|s| s.x_span_at_y(y))
+            .sorted_by_key(|r| r.start);
+
+        let mut covered_until = i64::MIN;
+        for r in spans {
+            let start = r.start.max(covered_until + 1);
+            if start <= r.end {
+                count += start.abs_diff(r.end) + 1;
+                covered_until = r.end;
             }
-        });
-        ranges.push(r);
+        }
+
+        let beacons = sensors
+            .iter()
+            .map(|s| s.beacon)
+            .filter(|b| b.y == y)
+            .unique()
+            .count();
+        count -= beacons as u64;
     }
 
-    let count = ranges.drain(..).map(|r| r.count()).sum();
+    count
+}
+
+fn part1(input: &str, y: i64) -> Result<u64, Error> {
+    let sensors = input
+        .lines()
+        .map(|l| l.parse::<Sensor>())
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(count)
+    Ok(coverage_in_band(&sensors, y..y + 1))
 }
 
-fn part2(input: &str, bounds: u64) -> Result<i64, Error> {
+fn find_distress_beacon(input: &str, bounds: u64) -> Result<(Position, i64), Error> {
     let sensors = input
         .lines()
         .map(|l| l.parse::<Sensor>())
         .collect::<Result<Vec<_>, _>>()?;
+    let sensors = drop_dominated(sensors);
+
+    let position = uncovered_point(&sensors, bounds).context("Could not find a solution")?;
+    let frequency = 4000000 * position.x + position.y;
+
+    Ok((position, frequency))
+}
 
+// The single gap must sit just outside the edges of several sensor ranges, so it is at an
+// intersection of those edge lines
+fn uncovered_point(sensors: &[Sensor], bounds: u64) -> Option<Position> {
     let mut a_coefficients = HashSet::new();
     let mut b_coefficients = HashSet::new();
-    for s in &sensors {
+    for s in sensors {
         let (a, b) = s.line_coefficients();
-        a_coefficients.extend(a.into_iter());
-        b_coefficients.extend(b.into_iter());
+        a_coefficients.extend(a);
+        b_coefficients.extend(b);
     }
 
     for a in &a_coefficients {
         for b in &b_coefficients {
+            // Lines of different parity only cross between grid positions
+            if (b - a).rem_euclid(2) != 0 {
+                continue;
+            }
             let intersection = Position::new((b - a) / 2, (b + a) / 2);
             if !intersection.in_bounds(bounds) {
                 continue;
@@ -134,12 +219,18 @@ fn part2(input: &str, bounds: u64) -> Result<i64, Error> {
                 .iter()
                 .all(|s| s.position.distance(&intersection) > s.distance)
             {
-                return Ok(4000000 * intersection.x + intersection.y);
+                return Some(intersection);
             }
         }
     }
 
-    bail!("Could not find a solution")
+    None
+}
+
+fn part2(input: &str, bounds: u64) -> Result<i64, Error> {
+    let (_, frequency) = find_distress_beacon(input, bounds)?;
+
+    Ok(frequency)
 }
 
 fn main() -> Result<(), Error> {
@@ -153,7 +244,12 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use itertools::Itertools;
+
+    use super::{
+        coverage_in_band, coverage_stats, find_distress_beacon, part1, part2, uncovered_point,
+        Position, Sensor,
+    };
 
     #[test]
     fn part1_example() {
@@ -165,6 +261,97 @@ mod tests {
         assert_eq!(part2(TEST_INPUT, 20).unwrap(), 56000011);
     }
 
+    #[test]
+    fn band_coverage() {
+        let sensors = test_sensors();
+        assert_eq!(
+            coverage_in_band(&sensors, 10..11),
+            part1(TEST_INPUT, 10).unwrap()
+        );
+
+        // Check the band cell by cell, independent of the span merging
+        let brute_force = |y: i64| {
+            (-100..100)
+                .map(|x| Position::new(x, y))
+                .filter(|p| sensors.iter().all(|s| s.beacon != *p))
+                .filter(|p| sensors.iter().any(|s| s.position.distance(p) <= s.distance))
+                .count() as u64
+        };
+        let per_row = (9..12).map(brute_force).collect::<Vec<_>>();
+        assert_eq!(per_row, [25, 26, 28]);
+        for (y, expected) in (9..12).zip(&per_row) {
+            assert_eq!(part1(TEST_INPUT, y).unwrap(), *expected);
+        }
+        assert_eq!(coverage_in_band(&sensors, 9..12), per_row.iter().sum());
+        assert_eq!(coverage_in_band(&sensors, 9..9), 0);
+    }
+
+    #[test]
+    fn distress_beacon_position() {
+        let (position, frequency) = find_distress_beacon(TEST_INPUT, 20).unwrap();
+        assert_eq!(position, Position::new(14, 11));
+        assert_eq!(frequency, 56000011);
+    }
+
+    #[test]
+    fn duplicate_sensor_is_dropped() {
+        let input = format!(
+            "{}Sensor at x=8, y=7: closest beacon is at x=2, y=10\n",
+            TEST_INPUT
+        );
+        let sensors: Vec<Sensor> = input.lines().map(|l| l.parse().unwrap()).collect();
+        let (kept, dropped) = coverage_stats(&test_sensors());
+
+        assert_eq!(coverage_stats(&sensors), (kept, dropped + 1));
+        assert_eq!(part2(&input, 20).unwrap(), 56000011);
+    }
+
+    fn test_sensors() -> Vec<Sensor> {
+        TEST_INPUT.lines().map(|l| l.parse().unwrap()).collect()
+    }
+
+    #[test]
+    fn uncovered_point_example() {
+        assert_eq!(
+            uncovered_point(&test_sensors(), 20),
+            Some(Position::new(14, 11))
+        );
+
+        let covering: Sensor = "Sensor at x=10, y=10: closest beacon is at x=10, y=30"
+            .parse()
+            .unwrap();
+        assert_eq!(uncovered_point(&[covering], 20), None);
+    }
+
+    #[test]
+    fn boundary_points() {
+        let sensor: Sensor = "Sensor at x=2, y=2: closest beacon is at x=3, y=2"
+            .parse()
+            .unwrap();
+        let points = sensor.boundary_points(20).collect_vec();
+        assert_eq!(points.len(), 8);
+        assert!(points.iter().all_unique());
+        assert!(points.iter().all(|p| p.distance(&sensor.position) == 2));
+
+        let corner: Sensor = "Sensor at x=0, y=0: closest beacon is at x=1, y=0"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            corner.boundary_points(20).collect_vec(),
+            [
+                Position::new(2, 0),
+                Position::new(1, 1),
+                Position::new(0, 2)
+            ]
+        );
+
+        // The distress beacon lies just outside some sensor's range
+        let beacon = Position::new(14, 11);
+        assert!(test_sensors()
+            .iter()
+            .any(|s| s.boundary_points(20).contains(&beacon)));
+    }
+
     static TEST_INPUT: &str = "Sensor at x=2, y=18: closest beacon is at x=-2, y=15
 Sensor at x=9, y=16: closest beacon is at x=10, y=16
 Sensor at x=13, y=2: closest beacon is at x=15, y=3
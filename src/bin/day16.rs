@@ -1,89 +1,205 @@
-use anyhow::{bail, Error};
+use anyhow::{bail, Context, Error};
 use bitvec::prelude::{BitArray, Lsb0};
-use itertools::{iproduct, Itertools};
+use itertools::iproduct;
+#[cfg(test)]
+use itertools::Itertools;
+use lazy_static::lazy_static;
 use ndarray::Array3;
-use std::{cmp::Reverse, collections::HashMap, fs};
+use regex::Regex;
+use std::{
+    borrow::Cow,
+    cmp::Reverse,
+    collections::{HashMap, VecDeque},
+    fs,
+};
+#[cfg(test)]
+use std::{
+    cell::RefCell,
+    fmt::Write,
+    ops::{Deref, DerefMut},
+};
 
 #[derive(Debug)]
 struct Valve<'a> {
-    name: &'a str,
+    name: Cow<'a, str>,
     flow: u16,
-    reachable: Vec<&'a str>,
+    reachable: Vec<Cow<'a, str>>,
+}
+
+impl Valve<'_> {
+    #[cfg(test)]
+    fn into_owned(self) -> Valve<'static> {
+        Valve {
+            name: Cow::Owned(self.name.into_owned()),
+            flow: self.flow,
+            reachable: self
+                .reachable
+                .into_iter()
+                .map(|r| Cow::Owned(r.into_owned()))
+                .collect(),
+        }
+    }
 }
 
 impl<'a> TryFrom<&'a str> for Valve<'a> {
     type Error = Error;
 
     fn try_from(s: &'a str) -> Result<Self, Self::Error> {
-        let words = s.split_ascii_whitespace().collect_vec();
-        let name = words[1];
-        let rate = words[4][5..words[4].len() - 1].parse::<u16>()?;
-        let neighbors = words[9..].iter().map(|&n| n.strip_suffix(',').unwrap_or(n));
+        lazy_static! {
+            static ref VALVE_RE: Regex = Regex::new(
+                r"^Valve (\w+) has flow rate=(\d+); (?:tunnels lead to valves|tunnel leads to valve) (\w+(?:, \w+)*)$"
+            )
+            .unwrap();
+        }
+        let captures = VALVE_RE
+            .captures(s.trim())
+            .with_context(|| format!("input '{}' did not match pattern", s))?;
+
+        let name = captures.get(1).unwrap().as_str();
+        let rate = captures[2].parse::<u16>()?;
+        let neighbors = captures.get(3).unwrap().as_str().split(", ");
 
         Ok(Valve {
-            name,
+            name: Cow::Borrowed(name),
             flow: rate,
-            reachable: neighbors.collect(),
+            reachable: neighbors.map(Cow::Borrowed).collect(),
         })
     }
 }
 
+// Caps the opened-set dimension of the DP table at 2^16 entries
+const MAX_WITH_FLOW: usize = 16;
+
+// Refuse to allocate a DP table larger than this
+const DEFAULT_MAX_SCORE_BYTES: usize = 2 << 30;
+
 struct SolveContext<'a> {
     valves: Vec<Valve<'a>>,
-    name_idx: HashMap<&'a str, usize>,
+    #[cfg(test)]
+    name_idx: HashMap<Cow<'a, str>, usize>,
     adjacency: Vec<Vec<usize>>,
+    // Positions the DP can move to from each position, and how many minutes that takes
+    moves: Vec<Vec<(usize, usize)>>,
     n_with_flow: usize,
+    start: usize,
     score: Array3<u16>,
+    // BFS distances from a given valve to every other valve, filled on demand
+    #[cfg(test)]
+    distances: RefCell<HashMap<usize, Vec<Option<u16>>>>,
 }
 
 impl<'a> TryFrom<&'a str> for SolveContext<'a> {
     type Error = Error;
 
     fn try_from(input: &'a str) -> Result<Self, Error> {
+        Self::try_from_with_start(input, "AA")
+    }
+}
+
+impl<'a> SolveContext<'a> {
+    fn try_from_with_start(input: &'a str, start: &str) -> Result<Self, Error> {
+        Self::try_from_with_limits(input, start, DEFAULT_MAX_SCORE_BYTES)
+    }
+
+    fn try_from_with_limits(
+        input: &'a str,
+        start: &str,
+        max_score_bytes: usize,
+    ) -> Result<Self, Error> {
         let mut valves = input
             .lines()
             .map(|l| l.try_into())
             .collect::<Result<Vec<Valve>, _>>()?;
 
-        valves.sort_unstable_by_key(|v| Reverse((v.flow, v.name == "AA")));
+        valves.sort_unstable_by_key(|v| Reverse((v.flow, v.name == start)));
 
         let name_idx: HashMap<_, _> = valves
             .iter()
             .enumerate()
-            .map(|(i, v)| (v.name, i))
+            .map(|(i, v)| (v.name.clone(), i))
             .collect();
 
-        let adjacency = valves
+        let start = *name_idx
+            .get(start)
+            .with_context(|| format!("could not find start valve '{}'", start))?;
+
+        let adjacency: Vec<Vec<usize>> = valves
             .iter()
-            .map(|v| v.reachable.iter().map(|r| name_idx[r]).collect())
+            .map(|v| v.reachable.iter().map(|r| name_idx[r.as_ref()]).collect())
+            .collect();
+        let moves = adjacency
+            .iter()
+            .map(|neighbors| neighbors.iter().map(|&n| (n, 1)).collect())
             .collect();
 
         let n_with_flow = valves.iter().filter(|v| v.flow > 0).count();
 
-        if n_with_flow > 16 {
+        if n_with_flow > MAX_WITH_FLOW {
             bail!("too many nodes with flow for u32 bitset to handle");
         }
         let opened_set_size = 1 << n_with_flow;
 
-        let score = Array3::default([30, valves.len(), opened_set_size]);
+        let shape = [30, valves.len(), opened_set_size];
+        let score_bytes = shape.iter().product::<usize>() * size_of::<u16>();
+        if score_bytes > max_score_bytes {
+            bail!(
+                "DP table for {} valves, {} with flow, would take {} bytes, over the limit of {}",
+                valves.len(),
+                n_with_flow,
+                score_bytes,
+                max_score_bytes
+            );
+        }
+        let score = Array3::default(shape);
 
         Ok(SolveContext {
+            #[cfg(test)]
             name_idx,
             valves,
             n_with_flow,
+            start,
             adjacency,
+            moves,
             score,
+            #[cfg(test)]
+            distances: RefCell::default(),
         })
     }
-}
 
-impl<'a> SolveContext<'a> {
+    // Zero-flow valves other than the start are only ever walked through, so replace them with
+    // direct moves between the valves worth standing at. Shrinks the position dimension of the DP.
+    fn contract_zero_flow(&mut self) {
+        // Sorting puts the start right after the valves with flow, if it has none itself
+        let n_positions = self.n_with_flow.max(self.start + 1);
+
+        self.moves = (0..n_positions)
+            .map(|from| {
+                let distances = self.bfs_distances(from);
+                (0..n_positions)
+                    .filter(|&to| to != from)
+                    .filter_map(|to| Some((to, distances[to]? as usize)))
+                    .collect()
+            })
+            .collect();
+
+        let shape = self.score.shape();
+        self.score = Array3::default([shape[0], n_positions, shape[2]]);
+    }
+
+    #[cfg(test)]
+    fn score_bytes(&self) -> usize {
+        self.score.len() * size_of::<u16>()
+    }
+
+    // Valves are sorted so that only indices below n_with_flow have flow, and only those fit in the
+    // opened bitset. Callers must not try to open anything else.
     fn score_for_opening(
         &self,
         time_remaining: usize,
         standing_at: usize,
         opened: BitArray<usize>,
     ) -> u16 {
+        debug_assert!(standing_at < self.n_with_flow);
         let opening_score = self.valves[standing_at].flow * time_remaining as u16;
 
         let mut opened = opened;
@@ -98,13 +214,145 @@ impl<'a> SolveContext<'a> {
         &self,
         time_remaining: usize,
         moving_to: usize,
+        minutes: usize,
         opened: BitArray<usize>,
     ) -> u16 {
-        self.score[(time_remaining - 1, moving_to, opened.into_inner())]
+        self.score[(time_remaining - minutes, moving_to, opened.into_inner())]
+    }
+
+    #[cfg(test)]
+    fn distance(&self, from: &str, to: &str) -> Option<u16> {
+        let from = *self.name_idx.get(from)?;
+        let to = *self.name_idx.get(to)?;
+
+        let mut distances = self.distances.borrow_mut();
+        let from_distances = distances
+            .entry(from)
+            .or_insert_with(|| self.bfs_distances(from));
+
+        from_distances[to]
+    }
+
+    fn bfs_distances(&self, from: usize) -> Vec<Option<u16>> {
+        let mut distances = vec![None; self.valves.len()];
+        let mut queue = VecDeque::new();
+        distances[from] = Some(0);
+        queue.push_back((from, 0));
+
+        while let Some((node, d)) = queue.pop_front() {
+            for &neighbor in &self.adjacency[node] {
+                if distances[neighbor].is_none() {
+                    distances[neighbor] = Some(d + 1);
+                    queue.push_back((neighbor, d + 1));
+                }
+            }
+        }
+
+        distances
+    }
+
+    // Pressure released by walking from the start and opening the given valves in order,
+    // independent of the DP
+    #[cfg(test)]
+    fn evaluate_plan(&self, order: &[&str], total_minutes: usize) -> Result<u16, Error> {
+        let breakdown = self.pressure_breakdown(order, total_minutes)?;
+
+        Ok(breakdown.iter().map(|&(_, released)| released).sum())
+    }
+
+    // Like evaluate_plan, but attributing the pressure to each opened valve
+    #[cfg(test)]
+    fn pressure_breakdown(
+        &self,
+        order: &[&str],
+        total_minutes: usize,
+    ) -> Result<Vec<(&str, u16)>, Error> {
+        let mut at = self.valves[self.start].name.as_ref();
+        let mut minute = 0;
+        let mut breakdown = Vec::with_capacity(order.len());
+
+        for &valve in order {
+            let idx = *self
+                .name_idx
+                .get(valve)
+                .with_context(|| format!("unknown valve '{}'", valve))?;
+            let d = self
+                .distance(at, valve)
+                .with_context(|| format!("valve '{}' is unreachable from '{}'", valve, at))?;
+
+            // Walk there, then spend a minute opening it
+            minute += d as usize + 1;
+            if minute > total_minutes {
+                bail!(
+                    "opening '{}' finishes at minute {}, past the budget of {}",
+                    valve,
+                    minute,
+                    total_minutes
+                );
+            }
+
+            let name = self.valves[idx].name.as_ref();
+            breakdown.push((
+                name,
+                self.valves[idx].flow * (total_minutes - minute) as u16,
+            ));
+            at = name;
+        }
+
+        Ok(breakdown)
+    }
+
+    // GraphViz graph of the tunnels, with valves that have no flow dashed
+    #[cfg(test)]
+    fn to_dot(&self) -> String {
+        let mut dot = String::from("graph valves {\n");
+        let mut valves = self.valves.iter().collect::<Vec<_>>();
+        valves.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+
+        for v in &valves {
+            let style = if v.flow == 0 { ", style=dashed" } else { "" };
+            writeln!(
+                dot,
+                "    {} [label=\"{} ({})\"{}];",
+                v.name, v.name, v.flow, style
+            )
+            .unwrap();
+        }
+        for v in &valves {
+            for r in v.reachable.iter().sorted() {
+                // Tunnels are listed from both ends, only draw them once
+                let listed_back = self
+                    .name_idx
+                    .get(r)
+                    .is_some_and(|&i| self.valves[i].reachable.contains(&v.name));
+                if v.name < *r || !listed_back {
+                    writeln!(dot, "    {} -- {};", v.name, r).unwrap();
+                }
+            }
+        }
+
+        dot.push_str("}\n");
+        dot
+    }
+
+    // Best pressure releasable from the start with t minutes remaining, for t in 0..=30
+    #[cfg(test)]
+    fn pressure_curve(&self) -> Vec<u16> {
+        std::iter::once(0)
+            .chain((0..self.score.shape()[0]).map(|t| self.score[(t, self.start, 0)]))
+            .collect()
     }
 
     // dynamic programming ftw
-    fn solve(&mut self) {
+    fn solve(&mut self) -> Result<(), Error> {
+        if self.n_with_flow > MAX_WITH_FLOW {
+            bail!(
+                "{} valves with flow exceed the opened bitset width of {}",
+                self.n_with_flow,
+                MAX_WITH_FLOW
+            );
+        }
+
         let shape = self.score.shape().to_owned();
         for (time_remaining, standing_at, opened) in iproduct!(
             (1..shape[0]),
@@ -119,17 +367,66 @@ impl<'a> SolveContext<'a> {
                 score = score.max(self.score_for_opening(time_remaining, standing_at, opened));
             }
 
-            for &moving_to in &self.adjacency[standing_at] {
-                score = score.max(self.score_for_moving(time_remaining, moving_to, opened));
+            for &(moving_to, minutes) in &self.moves[standing_at] {
+                if minutes <= time_remaining {
+                    score = score.max(self.score_for_moving(
+                        time_remaining,
+                        moving_to,
+                        minutes,
+                        opened,
+                    ));
+                }
             }
 
             self.score[(time_remaining, standing_at, opened.into_inner())] = score;
         }
+
+        Ok(())
+    }
+}
+
+// A context that does not borrow from its input, e.g. to keep around in a cache
+#[cfg(test)]
+struct OwnedSolveContext(SolveContext<'static>);
+
+#[cfg(test)]
+impl From<SolveContext<'_>> for OwnedSolveContext {
+    fn from(ctx: SolveContext<'_>) -> Self {
+        OwnedSolveContext(SolveContext {
+            valves: ctx.valves.into_iter().map(Valve::into_owned).collect(),
+            name_idx: ctx
+                .name_idx
+                .into_iter()
+                .map(|(name, i)| (Cow::Owned(name.into_owned()), i))
+                .collect(),
+            adjacency: ctx.adjacency,
+            moves: ctx.moves,
+            n_with_flow: ctx.n_with_flow,
+            start: ctx.start,
+            score: ctx.score,
+            distances: ctx.distances,
+        })
+    }
+}
+
+#[cfg(test)]
+impl Deref for OwnedSolveContext {
+    type Target = SolveContext<'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+impl DerefMut for OwnedSolveContext {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
     }
 }
 
 fn part1(ctx: &SolveContext) -> Result<u16, Error> {
-    Ok(ctx.score[(29, ctx.name_idx["AA"], 0)])
+    Ok(ctx.score[(29, ctx.start, 0)])
 }
 
 fn part2(ctx: &SolveContext) -> Result<u16, Error> {
@@ -139,8 +436,8 @@ fn part2(ctx: &SolveContext) -> Result<u16, Error> {
     for human_ignore in 0..opened_set_size {
         let elephant_ignore = (!human_ignore) & mask;
 
-        let human_score = ctx.score[(25, ctx.name_idx["AA"], human_ignore)];
-        let elephant_score = ctx.score[(25, ctx.name_idx["AA"], elephant_ignore)];
+        let human_score = ctx.score[(25, ctx.start, human_ignore)];
+        let elephant_score = ctx.score[(25, ctx.start, elephant_ignore)];
         let score = human_score + elephant_score;
         max_score = max_score.max(score);
     }
@@ -150,7 +447,8 @@ fn part2(ctx: &SolveContext) -> Result<u16, Error> {
 fn main() -> Result<(), Error> {
     let input = fs::read_to_string("input/16")?;
     let mut ctx = SolveContext::try_from(input.as_str())?;
-    ctx.solve();
+    ctx.contract_zero_flow();
+    ctx.solve()?;
 
     println!("Part 1: {}", part1(&ctx)?);
     println!("Part 2: {}", part2(&ctx)?);
@@ -160,19 +458,184 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{part1, part2, SolveContext};
+    use bitvec::prelude::BitArray;
+
+    use crate::{part1, part2, OwnedSolveContext, SolveContext, Valve};
 
     #[test]
     fn part1_example() {
         let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
-        ctx.solve();
+        ctx.solve().unwrap();
+        assert_eq!(part1(&ctx).unwrap(), 1651);
+    }
+
+    #[test]
+    fn parse_valve_phrasings() {
+        let v =
+            Valve::try_from("Valve AA has flow rate=0; tunnels lead to valves DD, II, BB").unwrap();
+        assert_eq!(
+            (v.name.as_ref(), v.flow, v.reachable),
+            ("AA", 0, vec!["DD".into(), "II".into(), "BB".into()])
+        );
+
+        let v = Valve::try_from("Valve HH has flow rate=22; tunnel leads to valve GG").unwrap();
+        assert_eq!(
+            (v.name.as_ref(), v.flow, v.reachable),
+            ("HH", 22, vec!["GG".into()])
+        );
+
+        assert!(Valve::try_from("Valve HH has flow rate=22; tunnel leads to valves GG").is_err());
+        assert!(Valve::try_from("Valve HH has flow rate=22").is_err());
+    }
+
+    #[test]
+    fn pressure_curve_example() {
+        let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        ctx.solve().unwrap();
+
+        let curve = ctx.pressure_curve();
+        assert_eq!(curve.len(), 31);
+        assert_eq!(curve[0], 0);
+        assert_eq!(curve[30], 1651);
+        assert!(curve.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn renamed_start_valve() {
+        let input = TEST_INPUT.replace("AA", "ZZ");
+        assert!(SolveContext::try_from(input.as_str()).is_err());
+
+        let mut ctx = SolveContext::try_from_with_start(&input, "ZZ").unwrap();
+        ctx.solve().unwrap();
+        assert_eq!(part1(&ctx).unwrap(), 1651);
+    }
+
+    #[test]
+    fn open_last_valve_with_flow() {
+        let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        ctx.solve().unwrap();
+
+        // CC is the valve with the lowest nonzero flow, so it sits right at the boundary
+        let last = ctx.n_with_flow - 1;
+        assert_eq!(ctx.valves[last].name, "CC");
+        assert_eq!(ctx.score_for_opening(1, last, BitArray::ZERO), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn open_valve_without_flow() {
+        let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        ctx.solve().unwrap();
+        ctx.score_for_opening(1, ctx.n_with_flow, BitArray::ZERO);
+    }
+
+    #[test]
+    fn solve_rejects_wide_bitset() {
+        let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        ctx.n_with_flow = 17;
+        assert!(ctx.solve().is_err());
+    }
+
+    #[test]
+    fn distance_example() {
+        let ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        assert_eq!(ctx.distance("AA", "HH"), Some(5));
+        assert_eq!(ctx.distance("HH", "AA"), Some(5));
+        assert_eq!(ctx.distance("AA", "AA"), Some(0));
+        assert_eq!(ctx.distance("AA", "ZZ"), None);
+    }
+
+    #[test]
+    fn evaluate_example_plan() {
+        let ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        let plan = ["DD", "BB", "JJ", "HH", "EE", "CC"];
+        assert_eq!(ctx.evaluate_plan(&plan, 30).unwrap(), 1651);
+
+        assert!(ctx.evaluate_plan(&plan, 20).is_err());
+        assert!(ctx.evaluate_plan(&["DD", "ZZ"], 30).is_err());
+    }
+
+    #[test]
+    fn example_pressure_breakdown() {
+        let ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        let plan = ["DD", "BB", "JJ", "HH", "EE", "CC"];
+        let breakdown = ctx.pressure_breakdown(&plan, 30).unwrap();
+
+        assert_eq!(
+            breakdown,
+            [
+                ("DD", 20 * 28),
+                ("BB", 13 * 25),
+                ("JJ", 21 * 21),
+                ("HH", 22 * 13),
+                ("EE", 3 * 9),
+                ("CC", 2 * 6)
+            ]
+        );
+        assert_eq!(breakdown.iter().map(|&(_, p)| p).sum::<u16>(), 1651);
+    }
+
+    #[test]
+    fn score_memory_limit() {
+        let ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        // 30 minutes, 10 valves, 6 of which have flow
+        assert_eq!(ctx.score_bytes(), 30 * 10 * 64 * 2);
+
+        assert!(SolveContext::try_from_with_limits(TEST_INPUT, "AA", 38400).is_ok());
+        let err = SolveContext::try_from_with_limits(TEST_INPUT, "AA", 38399)
+            .err()
+            .unwrap();
+        assert_eq!(
+            err.to_string(),
+            "DP table for 10 valves, 6 with flow, would take 38400 bytes, over the limit of 38399"
+        );
+    }
+
+    #[test]
+    fn dot_output() {
+        let ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        let dot = ctx.to_dot();
+
+        assert!(dot.starts_with("graph valves {\n"));
+        assert!(dot.contains("    AA [label=\"AA (0)\", style=dashed];\n"));
+        assert!(dot.contains("    BB [label=\"BB (13)\"];\n"));
+        assert!(dot.contains("    BB -- CC;\n"));
+        assert!(!dot.contains("CC -- BB"));
+        assert_eq!(dot.matches(" -- ").count(), 10);
+    }
+
+    #[test]
+    fn contracted_example() {
+        let mut full = SolveContext::try_from(TEST_INPUT).unwrap();
+        full.solve().unwrap();
+
+        let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
+        ctx.contract_zero_flow();
+        // The 6 valves with flow, plus AA
+        assert_eq!(ctx.score.shape()[1], 7);
+
+        ctx.solve().unwrap();
+        assert_eq!(part1(&ctx).unwrap(), 1651);
+        assert_eq!(part2(&ctx).unwrap(), 1707);
+        assert_eq!(ctx.pressure_curve(), full.pressure_curve());
+    }
+
+    #[test]
+    fn owned_context() {
+        let mut ctx = {
+            let input = TEST_INPUT.to_string();
+            OwnedSolveContext::from(SolveContext::try_from(input.as_str()).unwrap())
+        };
+        ctx.solve().unwrap();
+
         assert_eq!(part1(&ctx).unwrap(), 1651);
+        assert_eq!(ctx.distance("AA", "HH"), Some(5));
     }
 
     #[test]
     fn part2_example() {
         let mut ctx = SolveContext::try_from(TEST_INPUT).unwrap();
-        ctx.solve();
+        ctx.solve().unwrap();
         assert_eq!(part2(&ctx).unwrap(), 1707);
     }
 
@@ -80,6 +80,20 @@ impl Chamber {
         self.truncated_rows + self.rows.len() - 7
     }
 
+    // Per column, from the left, the height of its topmost rock. Includes truncated rows.
+    #[cfg(test)]
+    fn column_heights(&self) -> [usize; 7] {
+        let mut heights = [self.truncated_rows; 7];
+        for (y, row) in self.rows.iter().enumerate() {
+            for (column, height) in heights.iter_mut().enumerate() {
+                if (row >> (6 - column)) & 1 == 1 {
+                    *height = self.truncated_rows + y + 1;
+                }
+            }
+        }
+        heights
+    }
+
     fn start_height(&self) -> usize {
         self.rows.len() + 3 - EXTRA_ROWS
     }
@@ -99,7 +113,7 @@ impl Chamber {
         let rock_height = 4 - (rock.leading_zeros() as usize / 8);
         let new_top_height = height + rock_height;
         if let Some(h_diff) = new_top_height.checked_sub(self.rows.len() - EXTRA_ROWS) {
-            self.rows.extend(iter::repeat(LEFT_WALL).take(h_diff));
+            self.rows.extend(iter::repeat_n(LEFT_WALL, h_diff));
         }
     }
 
@@ -163,17 +177,10 @@ impl Chamber {
         }
     }
 
-    fn add_rock<'a>(
-        &mut self,
-        rocks: &mut impl Iterator<Item = (usize, &'a u32)>,
-        directions: &mut impl Iterator<Item = (usize, &'a Direction)>,
-    ) {
-        let (_, rock) = rocks.next().unwrap();
-        let mut rock = *rock;
+    fn add_rock(&mut self, mut rock: u32, mut next_direction: impl FnMut() -> Direction) {
         let mut height = self.start_height();
         loop {
-            let (_, &direction) = directions.next().unwrap();
-            rock = self.push_rock(rock, height, direction);
+            rock = self.push_rock(rock, height, next_direction());
             if height == 0 || !self.fits(rock, height - 1) {
                 break;
             }
@@ -184,56 +191,187 @@ impl Chamber {
     }
 }
 
-fn rock_fall(input: &str, total_rocks: usize) -> Result<usize, Error> {
-    let directions = input
-        .chars()
-        .filter(|c| !c.is_whitespace())
-        .map(|c| c.try_into())
-        .collect::<Result<Vec<Direction>, _>>()?;
+// A chamber along with where in the rock and jet cycles we are, so dropping can be resumed
+#[derive(Clone)]
+struct Simulation {
+    chamber: Chamber,
+    directions: Vec<Direction>,
+    rock_idx: usize,
+    jet_idx: usize,
+    // Pruning is the hot loop, so it can be done only every so many rocks
+    prune_interval: usize,
+    unpruned_rocks: usize,
+}
+
+impl Simulation {
+    fn new(input: &str) -> Result<Self, Error> {
+        let directions = input
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .map(|c| c.try_into())
+            .collect::<Result<Vec<Direction>, _>>()?;
+
+        // Jets are cycled through by index, which needs something to cycle
+        if directions.is_empty() {
+            bail!("jet pattern is empty");
+        }
 
-    let mut directions_inf = directions.iter().cycle().enumerate().peekable();
-    let mut rocks_inf = ALL_ROCKS.iter().cycle().enumerate().peekable();
+        Ok(Simulation {
+            chamber: Chamber::new(),
+            directions,
+            rock_idx: 0,
+            jet_idx: 0,
+            prune_interval: 1,
+            unpruned_rocks: 0,
+        })
+    }
+
+    #[cfg(test)]
+    fn with_prune_interval(mut self, prune_interval: usize) -> Result<Self, Error> {
+        if prune_interval == 0 {
+            bail!("prune interval must be at least 1");
+        }
+        self.prune_interval = prune_interval;
+
+        Ok(self)
+    }
+
+    fn step(&mut self, rocks: usize) {
+        for _ in 0..rocks {
+            let rock = ALL_ROCKS[self.rock_idx];
+            self.rock_idx = (self.rock_idx + 1) % ALL_ROCKS.len();
+
+            let (directions, jet_idx) = (&self.directions, &mut self.jet_idx);
+            self.chamber.add_rock(rock, || {
+                let direction = directions[*jet_idx];
+                *jet_idx = (*jet_idx + 1) % directions.len();
+                direction
+            });
+
+            self.unpruned_rocks += 1;
+            if self.unpruned_rocks >= self.prune_interval {
+                self.chamber.prune();
+                self.unpruned_rocks = 0;
+            }
+        }
+    }
 
-    let mut chamber = Chamber::new();
+    fn height(&self) -> usize {
+        self.chamber.tower_height()
+    }
+}
+
+// Skips ahead by whole cycles as it goes, main uses find_cycle instead but this stays as an
+// independent check
+#[cfg(test)]
+fn rock_fall(input: &str, total_rocks: usize) -> Result<usize, Error> {
+    rock_fall_with_prune_interval(input, total_rocks, 1)
+}
+
+#[cfg(test)]
+fn rock_fall_with_prune_interval(
+    input: &str,
+    total_rocks: usize,
+    prune_interval: usize,
+) -> Result<usize, Error> {
+    let mut sim = Simulation::new(input)?.with_prune_interval(prune_interval)?;
     let mut cache: BTreeMap<_, (usize, Chamber)> = BTreeMap::new();
 
     let mut n = 0;
     while n < total_rocks {
-        let n_rock = rocks_inf.peek().unwrap().0 % ALL_ROCKS.len();
-        let n_direction = directions_inf.peek().unwrap().0 % directions.len();
-        let n_state = chamber.as_vec();
-        let n_key = (n_rock, n_direction, n_state);
+        // Only a freshly pruned chamber is comparable to earlier ones
+        if sim.unpruned_rocks > 0 {
+            sim.step(1);
+            n += 1;
+            continue;
+        }
+
+        let n_key = (sim.rock_idx, sim.jet_idx, sim.chamber.as_vec());
 
         if let Some((earlier_n, earlier_chamber)) = cache.get(&n_key) {
             let remaining_n = total_rocks - n;
             let n_diff = n - earlier_n;
-            let h_diff = chamber.tower_height() - earlier_chamber.tower_height();
+            let h_diff = sim.height() - earlier_chamber.tower_height();
             let possible_jumps = remaining_n / n_diff;
 
             if possible_jumps > 0 {
                 let n_jump = possible_jumps * n_diff;
                 let h_jump = possible_jumps * h_diff;
                 n += n_jump;
-                chamber.skip(h_jump);
+                sim.chamber.skip(h_jump);
                 continue;
             }
         } else {
-            cache.insert(n_key, (n, chamber.clone()));
+            cache.insert(n_key, (n, sim.chamber.clone()));
         }
 
-        chamber.add_rock(&mut rocks_inf, &mut directions_inf);
-        chamber.prune();
+        sim.step(1);
 
         n += 1;
     }
 
-    Ok(chamber.tower_height())
+    Ok(sim.height())
+}
+
+// The chamber after dropping the given number of rocks, never pruned so it holds the whole tower
+#[cfg(test)]
+fn simulate_to(input: &str, rocks: usize) -> Result<Chamber, Error> {
+    let mut sim = Simulation::new(input)?.with_prune_interval(usize::MAX)?;
+    sim.step(rocks);
+
+    Ok(sim.chamber)
+}
+
+// Once the chamber repeats itself, the tower grows by height_per_period every period rocks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct CycleInfo {
+    start: usize,
+    period: usize,
+    height_per_period: usize,
+}
+
+// Also returns the tower height after each number of rocks up to the first repeat, which together
+// with the cycle is enough to answer any number of rocks
+fn find_cycle(input: &str) -> Result<(CycleInfo, Vec<usize>), Error> {
+    let mut sim = Simulation::new(input)?;
+    let mut seen = BTreeMap::new();
+    let mut prefix_heights = vec![];
+
+    loop {
+        let n = prefix_heights.len();
+        prefix_heights.push(sim.height());
+
+        let key = (sim.rock_idx, sim.jet_idx, sim.chamber.as_vec());
+        if let Some(&start) = seen.get(&key) {
+            let cycle = CycleInfo {
+                start,
+                period: n - start,
+                height_per_period: prefix_heights[n] - prefix_heights[start],
+            };
+            return Ok((cycle, prefix_heights));
+        }
+        seen.insert(key, n);
+
+        sim.step(1);
+    }
+}
+
+fn height_at(cycle: &CycleInfo, prefix_heights: &[usize], n: usize) -> usize {
+    if let Some(&h) = prefix_heights.get(n) {
+        return h;
+    }
+
+    let periods = (n - cycle.start) / cycle.period;
+    let offset = (n - cycle.start) % cycle.period;
+    prefix_heights[cycle.start + offset] + periods * cycle.height_per_period
 }
 
+#[cfg(test)]
 fn part1(input: &str) -> Result<usize, Error> {
     rock_fall(input, 2022)
 }
 
+#[cfg(test)]
 fn part2(input: &str) -> Result<usize, Error> {
     rock_fall(input, 1000000000000)
 }
@@ -241,15 +379,22 @@ fn part2(input: &str) -> Result<usize, Error> {
 fn main() -> Result<(), Error> {
     let input = fs::read_to_string("input/17")?;
 
-    println!("Part 1: {}", part1(&input)?);
-    println!("Part 2: {}", part2(&input)?);
+    let (cycle, prefix_heights) = find_cycle(&input)?;
+    println!("Part 1: {}", height_at(&cycle, &prefix_heights, 2022));
+    println!(
+        "Part 2: {}",
+        height_at(&cycle, &prefix_heights, 1000000000000)
+    );
 
     Ok(())
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use super::{
+        find_cycle, height_at, part1, part2, rock_fall, rock_fall_with_prune_interval, simulate_to,
+        Chamber, Simulation, EXTRA_ROWS, LEFT_WALL, ROCK_HORIZONTAL, ROCK_VERTICAL,
+    };
 
     #[test]
     fn part1_example() {
@@ -261,6 +406,113 @@ mod tests {
         assert_eq!(part2(TEST_INPUT).unwrap(), 1514285714288);
     }
 
+    #[test]
+    fn few_rocks() {
+        assert_eq!(rock_fall(TEST_INPUT, 0).unwrap(), 0);
+        // The first rock is the flat one
+        assert_eq!(rock_fall(TEST_INPUT, 1).unwrap(), 1);
+        assert_eq!(rock_fall(TEST_INPUT, 2).unwrap(), 4);
+        assert_eq!(rock_fall_with_prune_interval(TEST_INPUT, 1, 10).unwrap(), 1);
+    }
+
+    #[test]
+    fn prune_interval() {
+        for total_rocks in [2022, 1000000000000] {
+            assert_eq!(
+                rock_fall_with_prune_interval(TEST_INPUT, total_rocks, 10).unwrap(),
+                rock_fall_with_prune_interval(TEST_INPUT, total_rocks, 1).unwrap()
+            );
+        }
+        assert!(rock_fall_with_prune_interval(TEST_INPUT, 10, 0).is_err());
+    }
+
+    #[test]
+    fn empty_jet_pattern() {
+        for input in ["", " \n  \n"] {
+            let err = part1(input).unwrap_err();
+            assert_eq!(err.to_string(), "jet pattern is empty");
+        }
+    }
+
+    #[test]
+    fn column_heights() {
+        let mut chamber = Chamber::new();
+        assert_eq!(chamber.column_heights(), [0; 7]);
+
+        chamber.insert_rock(ROCK_HORIZONTAL, 0);
+        chamber.insert_rock(ROCK_VERTICAL, 1);
+        assert_eq!(chamber.column_heights(), [0, 0, 5, 1, 1, 1, 0]);
+
+        chamber.skip(10);
+        assert_eq!(chamber.column_heights(), [10, 10, 15, 11, 11, 11, 10]);
+    }
+
+    #[test]
+    fn resume_simulation() {
+        let mut resumed = Simulation::new(TEST_INPUT).unwrap();
+        resumed.step(1000);
+        resumed.step(1022);
+
+        let mut direct = Simulation::new(TEST_INPUT).unwrap();
+        direct.step(2022);
+
+        assert_eq!(resumed.height(), direct.height());
+        assert_eq!(resumed.height(), 3068);
+    }
+
+    #[test]
+    fn chamber_after_ten_rocks() {
+        // The tower from the puzzle description, bottom row first
+        let tower: [u8; 17] = [
+            0b1001_1110,
+            0b1000_1000,
+            0b1001_1100,
+            0b1111_1100,
+            0b1001_0100,
+            0b1001_0100,
+            0b1000_0100,
+            0b1000_0110,
+            0b1000_0110,
+            0b1011_1100,
+            0b1001_0000,
+            0b1011_1000,
+            0b1111_1110,
+            0b1110_0110,
+            0b1000_0110,
+            0b1000_0100,
+            0b1000_0100,
+        ];
+        let expected = [&tower[..], &[LEFT_WALL; EXTRA_ROWS]].concat();
+
+        let chamber = simulate_to(TEST_INPUT, 10).unwrap();
+        assert_eq!(chamber.as_vec(), expected);
+        assert_eq!(chamber.tower_height(), 17);
+
+        // Pruning only ever drops rows off the bottom
+        let mut pruned = Simulation::new(TEST_INPUT).unwrap();
+        pruned.step(10);
+        let truncated = pruned.chamber.truncated_rows;
+        assert_eq!(pruned.chamber.as_vec(), expected[truncated..]);
+    }
+
+    #[test]
+    fn height_from_cycle() {
+        let (cycle, prefix_heights) = find_cycle(TEST_INPUT).unwrap();
+        assert_eq!(prefix_heights.len(), cycle.start + cycle.period + 1);
+
+        assert_eq!(height_at(&cycle, &prefix_heights, 2022), 3068);
+        assert_eq!(
+            height_at(&cycle, &prefix_heights, 1000000000000),
+            1514285714288
+        );
+        for n in [0, 1, 2, 500, 5000] {
+            assert_eq!(
+                height_at(&cycle, &prefix_heights, n),
+                rock_fall(TEST_INPUT, n).unwrap()
+            );
+        }
+    }
+
     static TEST_INPUT: &str = ">>><<><>><<<>><>>><<<>>><<<><<<>><>><<>>
     ";
 }
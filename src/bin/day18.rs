@@ -1,3 +1,5 @@
+#[cfg(test)]
+use std::collections::HashMap;
 use std::{fs, str::FromStr};
 
 use anyhow::{Context, Error};
@@ -11,8 +13,8 @@ enum State {
     Lava,
 }
 
-fn try_build_grid(input: &str) -> Result<Array3<State>, Error> {
-    let points = input
+fn parse_points(input: &str) -> Result<Vec<(usize, usize, usize)>, Error> {
+    input
         .lines()
         .map(|l| {
             let parts = l
@@ -21,7 +23,11 @@ fn try_build_grid(input: &str) -> Result<Array3<State>, Error> {
                 .collect::<Result<Vec<_>, _>>()?;
             Result::<_, Error>::Ok((parts[0], parts[1], parts[2]))
         })
-        .collect::<Result<Vec<(usize, usize, usize)>, _>>()?;
+        .collect()
+}
+
+fn try_build_grid(input: &str) -> Result<Array3<State>, Error> {
+    let points = parse_points(input)?;
 
     let max_x = points
         .iter()
@@ -49,25 +55,110 @@ fn try_build_grid(input: &str) -> Result<Array3<State>, Error> {
     Ok(grid)
 }
 
-fn adjacent(p: (usize, usize, usize)) -> [(usize, usize, usize); 6] {
+// Dimensions of the grid built for an input, including padding
+#[cfg(test)]
+fn grid_dims(input: &str) -> Result<(usize, usize, usize), Error> {
+    Ok(try_build_grid(input)?.dim())
+}
+
+// Which cells around a cube count as its neighbors
+#[derive(Default, Debug, Clone, Copy)]
+enum Connectivity {
+    // The 6 cells sharing a face
+    #[default]
+    Faces,
+    // The 18 cells sharing a face or an edge
+    #[cfg(test)]
+    Edges,
+    // The 26 cells sharing a face, an edge or a corner
+    #[cfg(test)]
+    Corners,
+}
+
+// Offsets of every neighbor in the 3x3x3 block around a cell at (1, 1, 1), where at most
+// `max_differing` coordinates differ from the center
+const fn neighbor_offsets<const N: usize>(max_differing: usize) -> [(usize, usize, usize); N] {
+    let mut offsets = [(0, 0, 0); N];
+    let mut n = 0;
+    let mut i = 0;
+    while i < 27 {
+        let (dx, dy, dz) = (i / 9, i / 3 % 3, i % 3);
+        let differing = (dx != 1) as usize + (dy != 1) as usize + (dz != 1) as usize;
+        if differing > 0 && differing <= max_differing {
+            offsets[n] = (dx, dy, dz);
+            n += 1;
+        }
+        i += 1;
+    }
+    assert!(n == N, "wrong neighbor count");
+
+    offsets
+}
+
+const FACE_OFFSETS: [(usize, usize, usize); 6] = neighbor_offsets(1);
+#[cfg(test)]
+const EDGE_OFFSETS: [(usize, usize, usize); 18] = neighbor_offsets(2);
+#[cfg(test)]
+const CORNER_OFFSETS: [(usize, usize, usize); 26] = neighbor_offsets(3);
+
+impl Connectivity {
+    fn offsets(self) -> &'static [(usize, usize, usize)] {
+        match self {
+            Connectivity::Faces => &FACE_OFFSETS,
+            #[cfg(test)]
+            Connectivity::Edges => &EDGE_OFFSETS,
+            #[cfg(test)]
+            Connectivity::Corners => &CORNER_OFFSETS,
+        }
+    }
+}
+
+// Relies on padding, p must not be on the low edge of the grid
+fn adjacent(
+    p: (usize, usize, usize),
+    connectivity: Connectivity,
+) -> impl Iterator<Item = (usize, usize, usize)> {
     let (x, y, z) = p;
+    connectivity
+        .offsets()
+        .iter()
+        .map(move |&(dx, dy, dz)| (x + dx - 1, y + dy - 1, z + dz - 1))
+}
+
+// Like adjacent, but doesn't rely on padding to stay within the grid
+#[cfg(test)]
+fn neighbors_in_bounds<T>(
+    grid: &Array3<T>,
+    p: (usize, usize, usize),
+) -> impl Iterator<Item = (usize, usize, usize)> {
+    let (x, y, z) = p;
+    let (dx, dy, dz) = grid.dim();
     [
-        (x, y, z - 1),
-        (x, y, z + 1),
-        (x, y - 1, z),
-        (x, y + 1, z),
-        (x - 1, y, z),
-        (x + 1, y, z),
+        (Some(x), Some(y), z.checked_sub(1)),
+        (Some(x), Some(y), Some(z + 1)),
+        (Some(x), y.checked_sub(1), Some(z)),
+        (Some(x), Some(y + 1), Some(z)),
+        (x.checked_sub(1), Some(y), Some(z)),
+        (Some(x + 1), Some(y), Some(z)),
     ]
+    .into_iter()
+    .filter_map(move |n| match n {
+        (Some(x), Some(y), Some(z)) if x < dx && y < dy && z < dz => Some((x, y, z)),
+        _ => None,
+    })
 }
 
-fn count_exposed_sides(grid: &Array3<State>, counts_as_exposed: impl Fn(State) -> bool) -> usize {
+fn count_exposed_sides(
+    grid: &Array3<State>,
+    connectivity: Connectivity,
+    counts_as_exposed: impl Fn(State) -> bool,
+) -> usize {
     let mut exposed_sides = 0;
 
     for w in grid.windows([3, 3, 3]) {
         let p = (1, 1, 1);
         if matches!(w[p], State::Lava) {
-            for p in adjacent(p) {
+            for p in adjacent(p, connectivity) {
                 if counts_as_exposed(w[p]) {
                     exposed_sides += 1;
                 }
@@ -78,17 +169,39 @@ fn count_exposed_sides(grid: &Array3<State>, counts_as_exposed: impl Fn(State) -
     exposed_sides
 }
 
+// Linear in the number of cells: every face shared by two cells is seen twice
+#[cfg(test)]
+fn surface_area_faces(cells: &[(i64, i64, i64)]) -> usize {
+    let mut faces: HashMap<(i64, i64, i64), usize> = HashMap::new();
+    for &(x, y, z) in cells {
+        // Face centers in doubled coordinates, so they're all integers
+        let (x, y, z) = (2 * x, 2 * y, 2 * z);
+        for face in [
+            (x, y, z - 1),
+            (x, y, z + 1),
+            (x, y - 1, z),
+            (x, y + 1, z),
+            (x - 1, y, z),
+            (x + 1, y, z),
+        ] {
+            *faces.entry(face).or_default() += 1;
+        }
+    }
+
+    faces.values().filter(|&&count| count == 1).count()
+}
+
 fn part1(input: &str) -> Result<usize, Error> {
     let grid = try_build_grid(input)?;
 
-    let exposed_sides = count_exposed_sides(&grid, |p| matches!(p, State::Air));
+    let exposed_sides =
+        count_exposed_sides(&grid, Connectivity::default(), |p| matches!(p, State::Air));
 
     Ok(exposed_sides)
 }
 
-fn part2(input: &str) -> Result<usize, Error> {
-    let mut grid = try_build_grid(input)?;
-
+// Turns all air connected to the outside into steam
+fn fill_exterior_with_steam(grid: &mut Array3<State>) {
     // Fill edges with steam to avoid having to worry about index-out-of-bounds edge cases
     for i in 0..3 {
         let axis = Axis(i);
@@ -106,15 +219,41 @@ fn part2(input: &str) -> Result<usize, Error> {
     while let Some(p) = stack.pop() {
         grid[p] = State::Steam;
 
-        for n in adjacent(p) {
+        for n in adjacent(p, Connectivity::Faces) {
             if matches!(grid[n], State::Air) && !seen[n] {
                 seen[n] = true;
                 stack.push(n);
             }
         }
     }
+}
+
+#[cfg(test)]
+fn lava_volume(grid: &Array3<State>) -> usize {
+    grid.iter().filter(|s| matches!(s, State::Lava)).count()
+}
+
+// (volume, exterior surface)
+#[cfg(test)]
+fn stats(input: &str) -> Result<(usize, usize), Error> {
+    let mut grid = try_build_grid(input)?;
+    let volume = lava_volume(&grid);
+
+    fill_exterior_with_steam(&mut grid);
+    let exterior = count_exposed_sides(&grid, Connectivity::default(), |p| {
+        matches!(p, State::Steam)
+    });
 
-    let exposed_sides = count_exposed_sides(&grid, |p| matches!(p, State::Steam));
+    Ok((volume, exterior))
+}
+
+fn part2(input: &str) -> Result<usize, Error> {
+    let mut grid = try_build_grid(input)?;
+    fill_exterior_with_steam(&mut grid);
+
+    let exposed_sides = count_exposed_sides(&grid, Connectivity::default(), |p| {
+        matches!(p, State::Steam)
+    });
 
     Ok(exposed_sides)
 }
@@ -130,7 +269,12 @@ fn main() -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
-    use super::{part1, part2};
+    use ndarray::Array3;
+
+    use super::{
+        count_exposed_sides, grid_dims, neighbors_in_bounds, parse_points, part1, part2, stats,
+        surface_area_faces, try_build_grid, Connectivity, State,
+    };
 
     #[test]
     fn part1_example_small() {
@@ -142,11 +286,57 @@ mod tests {
         assert_eq!(part2("1,1,1\n2,1,1\n").unwrap(), 10);
     }
 
+    #[test]
+    fn connectivity_exposed_counts() {
+        let grid = try_build_grid("1,1,1\n2,1,1\n").unwrap();
+        let count =
+            |connectivity| count_exposed_sides(&grid, connectivity, |p| matches!(p, State::Air));
+
+        assert_eq!(count(Connectivity::Faces), 2 * 5);
+        assert_eq!(count(Connectivity::Edges), 2 * 17);
+        assert_eq!(count(Connectivity::Corners), 2 * 25);
+    }
+
     #[test]
     fn part1_example() {
         assert_eq!(part1(TEST_INPUT).unwrap(), 64);
     }
 
+    #[test]
+    fn neighbors_in_unpadded_grid() {
+        let mut grid: Array3<State> = Array3::default([3, 3, 3]);
+        grid[(0, 0, 0)] = State::Lava;
+
+        assert_eq!(neighbors_in_bounds(&grid, (0, 0, 0)).count(), 3);
+        assert_eq!(neighbors_in_bounds(&grid, (2, 2, 2)).count(), 3);
+        assert_eq!(neighbors_in_bounds(&grid, (1, 0, 0)).count(), 4);
+        assert_eq!(neighbors_in_bounds(&grid, (1, 1, 1)).count(), 6);
+    }
+
+    #[test]
+    fn grid_dims_example() {
+        // Max coordinates are (3, 3, 6), plus one to fit them and two for padding
+        assert_eq!(grid_dims(TEST_INPUT).unwrap(), (6, 6, 9));
+    }
+
+    #[test]
+    fn surface_area_faces_example() {
+        let cells = parse_points(TEST_INPUT)
+            .unwrap()
+            .into_iter()
+            .map(|(x, y, z)| (x as i64, y as i64, z as i64))
+            .collect::<Vec<_>>();
+
+        assert_eq!(surface_area_faces(&cells), 64);
+        assert_eq!(surface_area_faces(&cells), part1(TEST_INPUT).unwrap());
+    }
+
+    #[test]
+    fn stats_example() {
+        assert_eq!(stats(TEST_INPUT).unwrap(), (13, 58));
+        assert_eq!(stats("1,1,1\n2,1,1\n").unwrap(), (2, 10));
+    }
+
     #[test]
     fn part2_example() {
         assert_eq!(part2(TEST_INPUT).unwrap(), 58);
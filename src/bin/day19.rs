@@ -1,16 +1,20 @@
 use std::{
-    fs,
-    ops::{Index, IndexMut},
+    fs, iter,
+    ops::{Add, AddAssign, Index, IndexMut, Mul, Sub, SubAssign},
     str::FromStr,
 };
 
-use anyhow::{anyhow, Error};
-use itertools::iproduct;
+#[cfg(test)]
+use anyhow::Context;
+use anyhow::{anyhow, bail, Error};
+use itertools::{iproduct, Itertools};
 use nom::{
     bytes::complete::tag,
-    character::complete::digit1,
+    character::complete::{alpha1, digit1},
     combinator::{map_res, opt},
-    sequence::pair,
+    error::ErrorKind,
+    multi::separated_list1,
+    sequence::separated_pair,
     IResult,
 };
 
@@ -35,39 +39,97 @@ impl Resource {
     }
 }
 
-impl Index<Resource> for [usize; 3] {
+// An amount of each non-geode resource. Geodes are never spent, so they are tracked as score instead
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+struct ResourceCollection([usize; 3]);
+
+impl ResourceCollection {
+    fn iter(&self) -> impl Iterator<Item = &usize> {
+        self.0.iter()
+    }
+}
+
+impl From<[usize; 3]> for ResourceCollection {
+    fn from(amounts: [usize; 3]) -> Self {
+        ResourceCollection(amounts)
+    }
+}
+
+impl Index<Resource> for ResourceCollection {
     type Output = usize;
 
     fn index(&self, index: Resource) -> &Self::Output {
         match index {
-            Resource::Ore => &self[0],
-            Resource::Clay => &self[1],
-            Resource::Obsidian => &self[2],
+            Resource::Ore => &self.0[0],
+            Resource::Clay => &self.0[1],
+            Resource::Obsidian => &self.0[2],
             Resource::Geode => &0,
         }
     }
 }
 
-impl IndexMut<Resource> for [usize; 3] {
+impl IndexMut<Resource> for ResourceCollection {
     fn index_mut(&mut self, index: Resource) -> &mut Self::Output {
         match index {
-            Resource::Ore => &mut self[0],
-            Resource::Clay => &mut self[1],
-            Resource::Obsidian => &mut self[2],
+            Resource::Ore => &mut self.0[0],
+            Resource::Clay => &mut self.0[1],
+            Resource::Obsidian => &mut self.0[2],
             Resource::Geode => todo!(),
         }
     }
 }
 
-#[derive(Debug)]
+impl Add for ResourceCollection {
+    type Output = Self;
+
+    fn add(mut self, rhs: Self) -> Self::Output {
+        self += rhs;
+        self
+    }
+}
+
+impl AddAssign for ResourceCollection {
+    fn add_assign(&mut self, rhs: Self) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0) {
+            *a += b;
+        }
+    }
+}
+
+impl Sub for ResourceCollection {
+    type Output = Self;
+
+    fn sub(mut self, rhs: Self) -> Self::Output {
+        self -= rhs;
+        self
+    }
+}
+
+impl SubAssign for ResourceCollection {
+    fn sub_assign(&mut self, rhs: Self) {
+        for (a, b) in self.0.iter_mut().zip(rhs.0) {
+            *a -= b;
+        }
+    }
+}
+
+impl Mul<usize> for ResourceCollection {
+    type Output = Self;
+
+    fn mul(self, rhs: usize) -> Self::Output {
+        ResourceCollection(self.0.map(|a| a * rhs))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
 struct Blueprint {
     number: usize,
-    ore_robot: [usize; 3],
-    clay_robot: [usize; 3],
-    obsidian_robot: [usize; 3],
-    geode_robot: [usize; 3],
+    ore_robot: ResourceCollection,
+    clay_robot: ResourceCollection,
+    obsidian_robot: ResourceCollection,
+    geode_robot: ResourceCollection,
 
-    most_expensive: [usize; 3],
+    most_expensive: ResourceCollection,
 }
 
 impl Blueprint {
@@ -80,11 +142,11 @@ impl Blueprint {
     ) -> Self {
         let mut b = Blueprint {
             number,
-            ore_robot: ore,
-            clay_robot: clay,
-            obsidian_robot: obsidian,
-            geode_robot: geode,
-            most_expensive: [0, 0, 0],
+            ore_robot: ore.into(),
+            clay_robot: clay.into(),
+            obsidian_robot: obsidian.into(),
+            geode_robot: geode.into(),
+            most_expensive: ResourceCollection::default(),
         };
 
         for (r0, r1) in iproduct!(
@@ -96,13 +158,35 @@ impl Blueprint {
 
         b
     }
-    fn cost_of(&self, r: Resource) -> &[usize; 3] {
+    // Whether a geode robot can ever be built, starting out with only an ore robot
+    fn is_solvable(&self) -> bool {
+        let mut producible = vec![Resource::Ore];
+        loop {
+            let newly_producible = Resource::all()
+                .into_iter()
+                .filter(|r| !producible.contains(r))
+                .filter(|&r| {
+                    [Resource::Ore, Resource::Clay, Resource::Obsidian]
+                        .into_iter()
+                        .all(|c| self[r][c] == 0 || producible.contains(&c))
+                })
+                .collect_vec();
+            if newly_producible.is_empty() {
+                break;
+            }
+            producible.extend(newly_producible);
+        }
+
+        producible.contains(&Resource::Geode)
+    }
+
+    fn cost_of(&self, r: Resource) -> &ResourceCollection {
         &self[r]
     }
 }
 
 impl Index<Resource> for Blueprint {
-    type Output = [usize; 3];
+    type Output = ResourceCollection;
 
     fn index(&self, index: Resource) -> &Self::Output {
         match index {
@@ -114,13 +198,80 @@ impl Index<Resource> for Blueprint {
     }
 }
 
+impl FromStr for Blueprint {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Resource names are matched case-insensitively, and an unknown or repeated one fails the
+        // whole parse pointing at the offending word
+        fn parse_cost(s: &str) -> IResult<&str, [usize; 3]> {
+            let amount = map_res(digit1, |s: &str| s.parse::<usize>());
+            let (s, parts) =
+                separated_list1(tag(" and "), separated_pair(amount, tag(" "), alpha1))(s)?;
+
+            let mut cost = [0; 3];
+            let mut seen = [false; 3];
+            for (amount, name) in parts {
+                let i = ["ore", "clay", "obsidian"]
+                    .iter()
+                    .position(|r| r.eq_ignore_ascii_case(name))
+                    .ok_or_else(|| {
+                        nom::Err::Failure(nom::error::Error::new(name, ErrorKind::Tag))
+                    })?;
+                if seen[i] {
+                    return Err(nom::Err::Failure(nom::error::Error::new(
+                        name,
+                        ErrorKind::Verify,
+                    )));
+                }
+                seen[i] = true;
+                cost[i] = amount;
+            }
+
+            Ok((s, cost))
+        }
+        fn parse_blueprint(s: &str) -> IResult<&str, Blueprint> {
+            let (s, _) = tag("Blueprint ")(s)?;
+            let (s, number) = map_res(digit1, |s: &str| s.parse())(s)?;
+            let (s, _) = tag(": Each ore robot costs ")(s)?;
+            let (s, ore_robot) = parse_cost(s)?;
+            let (s, _) = tag(". Each clay robot costs ")(s)?;
+            let (s, clay_robot) = parse_cost(s)?;
+            let (s, _) = tag(". Each obsidian robot costs ")(s)?;
+            let (s, obsidian_robot) = parse_cost(s)?;
+            let (s, _) = tag(". Each geode robot costs ")(s)?;
+            let (s, geode_robot) = parse_cost(s)?;
+            let (s, _) = opt(tag("."))(s)?;
+
+            Ok((
+                s,
+                Blueprint::new(number, ore_robot, clay_robot, obsidian_robot, geode_robot),
+            ))
+        }
+
+        let (_, blueprint) = parse_blueprint(s).map_err(|e| match e {
+            nom::Err::Error(e) | nom::Err::Failure(e) => {
+                let token = e.input.split_whitespace().next().unwrap_or("end of input");
+                if e.code == ErrorKind::Verify {
+                    anyhow!("could not parse blueprint: '{}' is listed twice", token)
+                } else {
+                    anyhow!("could not parse blueprint: unexpected '{}'", token)
+                }
+            }
+            nom::Err::Incomplete(_) => anyhow!("could not parse blueprint: incomplete input"),
+        })?;
+
+        Ok(blueprint)
+    }
+}
+
 #[derive(Debug, Clone)]
 struct State<'a> {
     remaining_ticks: usize,
     score: usize,
 
-    resources: [usize; 3],
-    robots: [usize; 3],
+    resources: ResourceCollection,
+    robots: ResourceCollection,
 
     blueprint: &'a Blueprint,
     relevant: [Option<Resource>; 4],
@@ -131,18 +282,18 @@ impl<'a> State<'a> {
         State {
             remaining_ticks: max_ticks,
             score: 0,
-            resources: [0, 0, 0],
-            robots: [1, 0, 0],
+            resources: ResourceCollection::default(),
+            robots: [1, 0, 0].into(),
             blueprint,
             relevant: Resource::all().map(Some),
         }
     }
 
-    fn can_afford(&self, cost: &[usize; 3]) -> bool {
+    fn can_afford(&self, cost: &ResourceCollection) -> bool {
         self.resources.iter().zip(cost.iter()).all(|(r, c)| r >= c)
     }
 
-    fn ticks_until_afford(&self, cost: &[usize; 3]) -> Option<usize> {
+    fn ticks_until_afford(&self, cost: &ResourceCollection) -> Option<usize> {
         fn weird_div_ceil(a: usize, b: usize) -> Option<usize> {
             if a == 0 {
                 Some(0)
@@ -165,32 +316,26 @@ impl<'a> State<'a> {
 
     fn build_robot(&mut self, r: Resource) {
         match r {
-            Resource::Ore => self.robots[0] += 1,
-            Resource::Clay => self.robots[1] += 1,
-            Resource::Obsidian => self.robots[2] += 1,
+            Resource::Ore | Resource::Clay | Resource::Obsidian => self.robots[r] += 1,
             Resource::Geode => {
                 self.score += self.remaining_ticks;
             }
         };
     }
 
-    fn try_pay(&mut self, cost: &[usize; 3]) -> bool {
+    fn try_pay(&mut self, cost: &ResourceCollection) -> bool {
         if !self.can_afford(cost) {
             return false;
         }
 
-        for (r, c) in self.resources.iter_mut().zip(cost.iter()) {
-            *r -= c;
-        }
+        self.resources -= *cost;
 
         true
     }
 
     fn tick(&mut self, n_ticks: usize) {
         self.remaining_ticks -= n_ticks;
-        for i in 0..3 {
-            self.resources[i] += self.robots[i] * n_ticks;
-        }
+        self.resources += self.robots * n_ticks;
 
         for o in self.relevant.iter_mut() {
             if matches!(o, Some(Resource::Geode)) {
@@ -227,32 +372,59 @@ impl<'a> State<'a> {
     }
 
     fn upper_bound(&self) -> usize {
-        let mut cheaper_obsidian = self.blueprint.obsidian_robot;
-        cheaper_obsidian[0] = 0;
-        let mut cheaper_geode = self.blueprint.geode_robot;
-        cheaper_geode[0] = 0;
-
-        let mut s = self.clone();
-        while s.remaining_ticks > 0 {
-            let build_clay = s.try_pay(&self.blueprint.clay_robot);
-            let build_obsidian = s.try_pay(&cheaper_geode);
-            let build_geode = s.try_pay(&cheaper_obsidian);
+        self.score
+            + upper_bound(
+                self.resources,
+                self.robots,
+                self.blueprint,
+                self.remaining_ticks,
+            )
+    }
+}
 
-            s.tick(1);
-            s.build_robot(Resource::Ore);
-            if build_clay {
-                s.build_robot(Resource::Clay);
-            }
-            if build_obsidian {
-                s.build_robot(Resource::Obsidian);
-            }
-            if build_geode {
-                s.build_robot(Resource::Geode);
+// Geodes that could still be opened in the remaining ticks if ore were free for everything but
+// clay robots, and robots of every kind could be built in the same tick
+fn upper_bound(
+    mut resources: ResourceCollection,
+    mut robots: ResourceCollection,
+    blueprint: &Blueprint,
+    mut ticks: usize,
+) -> usize {
+    let mut cheaper_obsidian = blueprint.obsidian_robot;
+    cheaper_obsidian[Resource::Ore] = 0;
+    let mut cheaper_geode = blueprint.geode_robot;
+    cheaper_geode[Resource::Ore] = 0;
+
+    let mut geodes = 0;
+    while ticks > 0 {
+        let mut try_pay = |cost: &ResourceCollection| {
+            let affordable = cost.iter().zip(resources.iter()).all(|(c, r)| c <= r);
+            if affordable {
+                resources -= *cost;
             }
+            affordable
+        };
+        // Each robot must be paid with its own cost. Paying obsidian robots with the geode cost
+        // means they need obsidian to get going, so the bound undershoots and prunes the optimum.
+        let build_clay = try_pay(&blueprint.clay_robot);
+        let build_obsidian = try_pay(&cheaper_obsidian);
+        let build_geode = try_pay(&cheaper_geode);
+
+        ticks -= 1;
+        resources += robots;
+        robots[Resource::Ore] += 1;
+        if build_clay {
+            robots[Resource::Clay] += 1;
+        }
+        if build_obsidian {
+            robots[Resource::Obsidian] += 1;
+        }
+        if build_geode {
+            geodes += ticks;
         }
-
-        s.score
     }
+
+    geodes
 }
 
 // Several ideas going on here:
@@ -260,11 +432,30 @@ impl<'a> State<'a> {
 // * Branch on resource-next-buildable instead of next-time
 // * Restrict resources to those that will provide an actual benefit
 fn explore_blueprint(b: &Blueprint, max_ticks: usize) -> usize {
-    let mut stack = Vec::new();
-    stack.push(State::new(b, max_ticks));
+    explore_blueprint_capped(b, max_ticks, None)
+}
+
+// Gives up after exploring max_nodes states, returning the best score found so far
+fn explore_blueprint_capped(b: &Blueprint, max_ticks: usize, max_nodes: Option<usize>) -> usize {
+    if !b.is_solvable() {
+        return 0;
+    }
+
+    let root = State::new(b, max_ticks);
+    if root.upper_bound() == 0 {
+        return 0;
+    }
+
+    let mut stack = vec![root];
+    let mut explored = 0;
 
     let mut best_lower_bound = stack[0].score;
     while let Some(state) = stack.pop() {
+        if max_nodes.is_some_and(|max_nodes| explored >= max_nodes) {
+            break;
+        }
+        explored += 1;
+
         let lower_bound = state.score;
         best_lower_bound = best_lower_bound.max(lower_bound);
 
@@ -282,8 +473,46 @@ fn explore_blueprint(b: &Blueprint, max_ticks: usize) -> usize {
     best_lower_bound
 }
 
+// Explores the same blueprint for several horizons. Each horizon is its own search for now, but the
+// blueprint (and its most_expensive precompute) is shared between them.
+#[cfg(test)]
+fn explore_multi(b: &Blueprint, horizons: &[usize]) -> Vec<usize> {
+    horizons
+        .iter()
+        .map(|&max_ticks| explore_blueprint(b, max_ticks))
+        .collect()
+}
+
+// Blueprints are allowed to span multiple lines, as long as each starts with "Blueprint N:"
 fn parse_blueprints(input: &str) -> Result<Vec<Blueprint>, Error> {
-    input.lines().map(Blueprint::from_str).collect()
+    let normalized = input.split_ascii_whitespace().join(" ");
+    let starts = normalized
+        .match_indices("Blueprint ")
+        .map(|(i, _)| i)
+        .collect_vec();
+
+    if !normalized.is_empty() && starts.first() != Some(&0) {
+        bail!("expected input to start with a blueprint");
+    }
+
+    starts
+        .into_iter()
+        .chain(iter::once(normalized.len()))
+        .tuple_windows()
+        .map(|(start, end)| normalized[start..end].trim_end().parse())
+        .collect()
+}
+
+// Looks the blueprint up by its own number, not its position in the input
+#[cfg(test)]
+fn solve_blueprint_by_number(input: &str, number: usize, ticks: usize) -> Result<usize, Error> {
+    let blueprints = parse_blueprints(input)?;
+    let blueprint = blueprints
+        .iter()
+        .find(|b| b.number == number)
+        .with_context(|| format!("no blueprint with number {}", number))?;
+
+    Ok(explore_blueprint(blueprint, ticks))
 }
 
 fn part1(input: &str) -> Result<usize, Error> {
@@ -322,7 +551,12 @@ fn main() -> Result<(), Error> {
 mod tests {
     use std::str::FromStr;
 
-    use crate::{explore_blueprint, part1, Blueprint};
+    use itertools::Itertools;
+
+    use crate::{
+        explore_blueprint, explore_blueprint_capped, explore_multi, parse_blueprints, part1,
+        solve_blueprint_by_number, Blueprint, Resource, ResourceCollection, State,
+    };
 
     #[test]
     fn part1_example() {
@@ -340,56 +574,190 @@ mod tests {
         assert_eq!(explore_blueprint(&b[1], 32), 62);
     }
 
-    static TEST_INPUT: &str = "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
+    #[test]
+    fn blueprint_by_number() {
+        assert_eq!(solve_blueprint_by_number(TEST_INPUT, 2, 32).unwrap(), 62);
+
+        // Numbers need not match positions
+        let reversed = TEST_INPUT.lines().rev().join("\n");
+        assert_eq!(solve_blueprint_by_number(&reversed, 1, 24).unwrap(), 9);
+
+        let err = solve_blueprint_by_number(TEST_INPUT, 3, 24).unwrap_err();
+        assert_eq!(err.to_string(), "no blueprint with number 3");
+    }
+
+    #[test]
+    fn node_cap() {
+        let b = parse_blueprints(TEST_INPUT).unwrap();
+        assert_eq!(explore_blueprint_capped(&b[0], 24, Some(1_000_000)), 9);
+        assert_eq!(explore_blueprint_capped(&b[1], 24, Some(1_000_000)), 12);
+        assert_eq!(explore_blueprint_capped(&b[0], 32, Some(1_000_000)), 56);
+
+        // Hitting the cap still gives a valid, if not optimal, answer
+        assert!(explore_blueprint_capped(&b[0], 24, Some(10)) <= 9);
+    }
+
+    #[test]
+    fn multiple_horizons() {
+        let b = parse_blueprints(TEST_INPUT).unwrap();
+        assert_eq!(explore_multi(&b[0], &[24, 32]), [9, 56]);
+    }
+
+    #[test]
+    fn multiline_blueprint() {
+        let multiline = "Blueprint 1:
+  Each ore robot costs 4 ore. Each clay robot costs 2 ore.
+  Each obsidian robot costs 3 ore and 14 clay.
+  Each geode robot costs 2 ore and 7 obsidian.
+
 Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian.
 ";
-}
+        assert_eq!(
+            parse_blueprints(multiline).unwrap(),
+            parse_blueprints(TEST_INPUT).unwrap()
+        );
+        assert!(parse_blueprints("Each ore robot costs 4 ore.").is_err());
+    }
 
-impl FromStr for Blueprint {
-    type Err = Error;
+    #[test]
+    fn lenient_blueprint_parsing() {
+        let expected = TEST_INPUT
+            .lines()
+            .next()
+            .unwrap()
+            .parse::<Blueprint>()
+            .unwrap();
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        fn parse_cost_part<'a>(t: &'a str) -> impl Fn(&'a str) -> IResult<&'a str, usize> {
-            move |s: &'a str| {
-                let (s, amount) = map_res(digit1, |s: &str| s.parse())(s)?;
-                let (s, _) = tag(" ")(s)?;
-                let (s, _) = tag(t)(s)?;
-                Ok((s, amount))
-            }
+        let trailing = "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.  \n";
+        assert_eq!(trailing.parse::<Blueprint>().unwrap(), expected);
+
+        let chatty = "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian. Good luck!";
+        assert_eq!(chatty.parse::<Blueprint>().unwrap(), expected);
+
+        let no_period = "Blueprint 1: Each ore robot costs 4 Ore. Each clay robot costs 2 ORE. Each obsidian robot costs 3 ore and 14 Clay. Each geode robot costs 2 ore and 7 obsidian";
+        assert_eq!(no_period.parse::<Blueprint>().unwrap(), expected);
+
+        let misspelled = "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 caly. Each geode robot costs 2 ore and 7 obsidian.";
+        assert_eq!(
+            misspelled.parse::<Blueprint>().unwrap_err().to_string(),
+            "could not parse blueprint: unexpected 'caly'"
+        );
+
+        let repeated = "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 0 Ore.";
+        assert_eq!(
+            repeated.parse::<Blueprint>().unwrap_err().to_string(),
+            "could not parse blueprint: 'Ore' is listed twice"
+        );
+    }
+
+    #[test]
+    fn unsolvable_blueprint() {
+        for b in parse_blueprints(TEST_INPUT).unwrap() {
+            assert!(b.is_solvable());
         }
 
-        fn parse_cost(s: &str) -> IResult<&str, [usize; 3]> {
-            let (s, ore) = parse_cost_part("ore")(s)?;
+        // Clay robots need obsidian, which needs clay, so geodes are out of reach
+        let b = Blueprint::new(1, [4, 0, 0], [0, 0, 1], [3, 14, 0], [2, 0, 7]);
+        assert!(!b.is_solvable());
+        assert_eq!(explore_blueprint(&b, 32), 0);
 
-            let (s, opt_clay) = opt(pair(tag(" and "), parse_cost_part("clay")))(s)?;
-            let (s, opt_obsidian) = opt(pair(tag(" and "), parse_cost_part("obsidian")))(s)?;
+        // Ore robots needing clay is fine, since we already start with one
+        let b = Blueprint::new(1, [0, 1, 0], [2, 0, 0], [3, 14, 0], [2, 0, 7]);
+        assert!(b.is_solvable());
+    }
+
+    // The bound as it used to be computed, by simulating on a clone of the whole state
+    fn simulated_upper_bound(state: &State) -> usize {
+        let mut cheaper_obsidian = state.blueprint.obsidian_robot;
+        cheaper_obsidian[Resource::Ore] = 0;
+        let mut cheaper_geode = state.blueprint.geode_robot;
+        cheaper_geode[Resource::Ore] = 0;
 
-            let clay = opt_clay.map(|(_, v)| v).unwrap_or_default();
-            let obsidian = opt_obsidian.map(|(_, v)| v).unwrap_or_default();
+        let mut s = state.clone();
+        while s.remaining_ticks > 0 {
+            let build_clay = s.try_pay(&state.blueprint.clay_robot);
+            let build_obsidian = s.try_pay(&cheaper_obsidian);
+            let build_geode = s.try_pay(&cheaper_geode);
 
-            Ok((s, [ore, clay, obsidian]))
+            s.tick(1);
+            s.build_robot(Resource::Ore);
+            if build_clay {
+                s.build_robot(Resource::Clay);
+            }
+            if build_obsidian {
+                s.build_robot(Resource::Obsidian);
+            }
+            if build_geode {
+                s.build_robot(Resource::Geode);
+            }
         }
-        fn parse_blueprint(s: &str) -> IResult<&str, Blueprint> {
-            let (s, _) = tag("Blueprint ")(s)?;
-            let (s, number) = map_res(digit1, |s: &str| s.parse())(s)?;
-            let (s, _) = tag(": Each ore robot costs ")(s)?;
-            let (s, ore_robot) = parse_cost(s)?;
-            let (s, _) = tag(". Each clay robot costs ")(s)?;
-            let (s, clay_robot) = parse_cost(s)?;
-            let (s, _) = tag(". Each obsidian robot costs ")(s)?;
-            let (s, obsidian_robot) = parse_cost(s)?;
-            let (s, _) = tag(". Each geode robot costs ")(s)?;
-            let (s, geode_robot) = parse_cost(s)?;
 
-            Ok((
-                s,
-                Blueprint::new(number, ore_robot, clay_robot, obsidian_robot, geode_robot),
-            ))
+        s.score
+    }
+
+    #[test]
+    fn upper_bound_matches_simulation() {
+        for b in parse_blueprints(TEST_INPUT).unwrap() {
+            // Walk a few paths down the search tree, checking the bound at every state
+            for path in Resource::all().into_iter().permutations(3) {
+                let mut state = State::new(&b, 32);
+                assert_eq!(state.upper_bound(), simulated_upper_bound(&state));
+                for r in path.into_iter().cycle().take(12) {
+                    if !state.try_wait_and_build(r) {
+                        continue;
+                    }
+                    assert_eq!(state.upper_bound(), simulated_upper_bound(&state));
+                }
+            }
         }
+    }
 
-        let (_, blueprint) =
-            parse_blueprint(s).map_err(|e| anyhow!("could not parse blueprint: {:?}", e))?;
+    #[test]
+    fn upper_bound_is_admissible() {
+        let blueprints = parse_blueprints(TEST_INPUT).unwrap();
+        for (b, best_24, best_32) in [(&blueprints[0], 9, 56), (&blueprints[1], 12, 62)] {
+            assert!(State::new(b, 24).upper_bound() >= best_24);
+            assert!(State::new(b, 32).upper_bound() >= best_32);
+
+            // Every state along a path can still reach what the path ends up with
+            for path in Resource::all().into_iter().permutations(4) {
+                let mut state = State::new(b, 32);
+                let mut bounds = vec![state.upper_bound()];
+                for r in path.into_iter().cycle().take(40) {
+                    if state.try_wait_and_build(r) {
+                        bounds.push(state.upper_bound());
+                    }
+                }
+                assert!(bounds.iter().all(|&bound| bound >= state.score));
+            }
+        }
+    }
 
-        Ok(blueprint)
+    #[test]
+    fn resource_collection_arithmetic() {
+        let a = ResourceCollection::from([4, 2, 7]);
+        let b = ResourceCollection::from([1, 0, 3]);
+        let c = ResourceCollection::from([2, 5, 1]);
+        let zero = ResourceCollection::default();
+
+        assert_eq!(a + zero, a);
+        assert_eq!(a - zero, a);
+        assert_eq!((a + b) + c, a + (b + c));
+        assert_eq!(a + b, b + a);
+        assert_eq!((a + b) - b, a);
+        assert_eq!(a * 3, a + a + a);
+        assert_eq!((a + b) * 2, a * 2 + b * 2);
+
+        let mut d = a;
+        d += c;
+        d -= a;
+        assert_eq!(d, c);
+
+        assert_eq!(a[Resource::Obsidian], 7);
+        assert_eq!(a[Resource::Geode], 0);
     }
+
+    static TEST_INPUT: &str = "Blueprint 1: Each ore robot costs 4 ore. Each clay robot costs 2 ore. Each obsidian robot costs 3 ore and 14 clay. Each geode robot costs 2 ore and 7 obsidian.
+Blueprint 2: Each ore robot costs 2 ore. Each clay robot costs 3 ore. Each obsidian robot costs 3 ore and 8 clay. Each geode robot costs 3 ore and 12 obsidian.
+";
 }
@@ -1,70 +1,85 @@
-use std::{collections::HashSet, fmt::Display, fs, str::FromStr};
+use std::{cmp::Ordering, fmt::Display, fs, str::FromStr};
 
+#[cfg(test)]
+use anyhow::bail;
 use anyhow::{anyhow, Error};
 use itertools::Itertools;
 
-// Doubly linked list
+// Doubly linked list, moving each element in its original order by an offset computed from it
 #[derive(Debug)]
-struct List {
-    zero_idx: usize,
-    entries: Vec<ListEntry>,
+struct Mixer<T> {
+    entries: Vec<ListEntry<T>>,
 }
 #[derive(Debug)]
-struct ListEntry {
-    value: i64,
+struct ListEntry<T> {
+    value: T,
+    // Mixing order, independent of where the entry is stored
+    original_index: usize,
     prev: usize,
     next: usize,
 }
 
-impl Display for List {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut parts = Vec::new();
-        let mut seen = HashSet::new();
-        let mut cursor = 0;
-        while !seen.contains(&cursor) {
-            seen.insert(cursor);
-            parts.push(self.entries[cursor].value);
-            cursor = self.entries[cursor].next;
-        }
+impl<T> Mixer<T> {
+    fn new(values: Vec<T>) -> Self {
+        let len = values.len();
+        let entries = values
+            .into_iter()
+            .enumerate()
+            .map(|(pos, value)| ListEntry {
+                value,
+                original_index: pos,
+                prev: if pos > 0 { pos - 1 } else { len - 1 },
+                next: if pos < len - 1 { pos + 1 } else { 0 },
+            })
+            .collect();
 
-        f.debug_list().entries(parts.iter()).finish()
+        Mixer { entries }
     }
-}
-
-impl FromStr for List {
-    type Err = Error;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let values = s
-            .lines()
-            .map(|l| {
-                l.parse::<i64>()
-                    .map_err(|e| anyhow!("could parse input: {:?}", e))
+    // All values in list order, starting at the element stored at index i
+    fn values_from(&self, mut i: usize) -> Vec<&T> {
+        (0..self.entries.len())
+            .map(|_| {
+                let value = &self.entries[i].value;
+                i = self.entries[i].next;
+                value
             })
-            .collect::<Result<Vec<_>, _>>()?;
+            .collect()
+    }
 
-        let entries: Vec<_> = values
-            .iter()
-            .enumerate()
-            .map(|(pos, &value)| ListEntry {
-                value,
-                prev: if pos > 0 { pos - 1 } else { values.len() - 1 },
-                next: if pos < values.len() - 1 { pos + 1 } else { 0 },
-            })
-            .collect();
-        let zero_idx = entries
-            .iter()
-            .enumerate()
-            .filter(|(_, e)| e.value == 0)
-            .map(|(i, _)| i)
-            .exactly_one()
-            .map_err(|e| anyhow!("could not find index of zero: {}", e))?;
+    // Following next from 0 visits every entry exactly once, and prev undoes next
+    #[cfg(test)]
+    fn check_invariants(&self) -> Result<(), Error> {
+        let len = self.entries.len();
+        let mut seen = vec![false; len];
+        let mut cursor = 0;
+        for _ in 0..len {
+            if seen[cursor] {
+                bail!("entry {} is visited twice", cursor);
+            }
+            seen[cursor] = true;
 
-        Ok(List { zero_idx, entries })
+            let next = self.entries[cursor].next;
+            if next >= len {
+                bail!("entry {} points to next {}, out of range", cursor, next);
+            }
+            if self.entries[next].prev != cursor {
+                bail!(
+                    "entry {} has next {}, but its prev is {}",
+                    cursor,
+                    next,
+                    self.entries[next].prev
+                );
+            }
+            cursor = next;
+        }
+        if len > 0 && cursor != 0 {
+            bail!("list does not loop back to entry 0");
+        }
+
+        Ok(())
     }
-}
 
-impl List {
     fn remove(&mut self, i: usize) {
         let prev = self.entries[i].prev;
         let next = self.entries[i].next;
@@ -103,10 +118,14 @@ impl List {
         self.entries[i].prev = new_prev;
     }
 
-    fn mix(&mut self) {
-        for i in 0..self.entries.len() {
-            let value = self.entries[i].value;
-            let new_prev = self.seek(i, value, true);
+    fn mix_by(&mut self, offset: impl Fn(&T) -> i64) {
+        if self.entries.len() < 2 {
+            return;
+        }
+
+        let order = (0..self.entries.len()).sorted_by_key(|&i| self.entries[i].original_index);
+        for i in order {
+            let new_prev = self.seek(i, offset(&self.entries[i].value), true);
             if new_prev == i {
                 continue;
             }
@@ -114,11 +133,249 @@ impl List {
             self.insert(i, new_prev);
         }
     }
+}
 
-    fn score_coordinates(&self) -> i64 {
-        [1000, 2000, 3000]
-            .into_iter()
-            .map(|a| self.entries[self.seek(self.zero_idx, a, false)].value)
+#[derive(Debug)]
+struct List {
+    zero_idx: usize,
+    mixer: Mixer<i64>,
+}
+
+impl Display for List {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.mixer.values_from(0)).finish()
+    }
+}
+
+impl FromStr for List {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .lines()
+            .map(|l| {
+                l.parse::<i64>()
+                    .map_err(|e| anyhow!("could parse input: {:?}", e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let zero_idx = values
+            .iter()
+            .positions(|&v| v == 0)
+            .exactly_one()
+            .map_err(|e| anyhow!("could not find index of zero: {}", e))?;
+
+        Ok(List {
+            zero_idx,
+            mixer: Mixer::new(values),
+        })
+    }
+}
+
+impl List {
+    fn mix(&mut self) {
+        self.mixer.mix_by(|&v| v);
+    }
+
+    fn score_coordinates(&self, offsets: &[i64]) -> i64 {
+        offsets
+            .iter()
+            .map(|&a| self.mixer.entries[self.mixer.seek(self.zero_idx, a, false)].value)
+            .sum::<i64>()
+    }
+}
+
+const COORDINATE_OFFSETS: [i64; 3] = [1000, 2000, 3000];
+
+const NIL: usize = usize::MAX;
+
+// Implicit treap, i.e. a balanced tree ordered by position in the list. Nodes never move in
+// `nodes`, and we find a node's current position by walking up to the root, so moving an element
+// is O(log n) instead of a walk along the list.
+struct IndexedList {
+    zero_idx: usize,
+    root: usize,
+    nodes: Vec<TreapNode>,
+}
+
+struct TreapNode {
+    value: i64,
+    // Mixing order, as in ListEntry
+    original_index: usize,
+    priority: u64,
+    size: usize,
+    left: usize,
+    right: usize,
+    parent: usize,
+}
+
+impl From<List> for IndexedList {
+    fn from(list: List) -> Self {
+        // xorshift, we just need the priorities to be spread out
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+
+        // Nodes in list order starting from zero, which need not be the storage order
+        let entries = &list.mixer.entries;
+        let mut i = list.zero_idx;
+        let nodes = (0..entries.len())
+            .map(|_| {
+                let e = &entries[i];
+                i = e.next;
+
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                TreapNode {
+                    value: e.value,
+                    original_index: e.original_index,
+                    priority: seed,
+                    size: 1,
+                    left: NIL,
+                    right: NIL,
+                    parent: NIL,
+                }
+            })
+            .collect();
+
+        let mut out = IndexedList {
+            zero_idx: 0,
+            root: NIL,
+            nodes,
+        };
+        for i in 0..out.nodes.len() {
+            out.root = out.merge(out.root, i);
+        }
+        out
+    }
+}
+
+impl IndexedList {
+    fn size(&self, node: usize) -> usize {
+        if node == NIL {
+            0
+        } else {
+            self.nodes[node].size
+        }
+    }
+
+    fn update(&mut self, node: usize) {
+        let TreapNode { left, right, .. } = self.nodes[node];
+        self.nodes[node].size = 1 + self.size(left) + self.size(right);
+        for child in [left, right] {
+            if child != NIL {
+                self.nodes[child].parent = node;
+            }
+        }
+    }
+
+    fn detach(&mut self, node: usize) -> usize {
+        if node != NIL {
+            self.nodes[node].parent = NIL;
+        }
+        node
+    }
+
+    // Splits into a tree of the first k nodes and a tree of the rest
+    fn split(&mut self, node: usize, k: usize) -> (usize, usize) {
+        if node == NIL {
+            return (NIL, NIL);
+        }
+
+        let TreapNode { left, right, .. } = self.nodes[node];
+        let left_size = self.size(left);
+        let (a, b) = if left_size >= k {
+            let (a, b) = self.split(left, k);
+            self.nodes[node].left = b;
+            (a, node)
+        } else {
+            let (a, b) = self.split(right, k - left_size - 1);
+            self.nodes[node].right = a;
+            (node, b)
+        };
+        self.update(node);
+
+        (self.detach(a), self.detach(b))
+    }
+
+    fn merge(&mut self, a: usize, b: usize) -> usize {
+        if a == NIL {
+            return b;
+        }
+        if b == NIL {
+            return a;
+        }
+
+        let root = if self.nodes[a].priority > self.nodes[b].priority {
+            self.nodes[a].right = self.merge(self.nodes[a].right, b);
+            a
+        } else {
+            self.nodes[b].left = self.merge(a, self.nodes[b].left);
+            b
+        };
+        self.update(root);
+
+        self.detach(root)
+    }
+
+    fn position(&self, mut node: usize) -> usize {
+        let mut pos = self.size(self.nodes[node].left);
+        while self.nodes[node].parent != NIL {
+            let parent = self.nodes[node].parent;
+            if self.nodes[parent].right == node {
+                pos += self.size(self.nodes[parent].left) + 1;
+            }
+            node = parent;
+        }
+        pos
+    }
+
+    fn at_position(&self, mut pos: usize) -> usize {
+        let mut node = self.root;
+        loop {
+            let left = self.nodes[node].left;
+            let left_size = self.size(left);
+            match pos.cmp(&left_size) {
+                Ordering::Less => node = left,
+                Ordering::Equal => return node,
+                Ordering::Greater => {
+                    pos -= left_size + 1;
+                    node = self.nodes[node].right;
+                }
+            }
+        }
+    }
+
+    fn mix(&mut self) {
+        let len = self.nodes.len();
+        if len < 2 {
+            return;
+        }
+
+        let order = (0..len).sorted_by_key(|&i| self.nodes[i].original_index);
+        for i in order {
+            let pos = self.position(i);
+            let (before, rest) = self.split(self.root, pos);
+            let (node, after) = self.split(rest, 1);
+            debug_assert_eq!(node, i);
+            let without = self.merge(before, after);
+
+            // The list is circular, so inserting at the very end or the very start is the same
+            let new_pos = (pos as i64 + self.nodes[i].value).rem_euclid(len as i64 - 1) as usize;
+            let (before, after) = self.split(without, new_pos);
+            let with = self.merge(before, i);
+            self.root = self.merge(with, after);
+        }
+    }
+
+    fn score_coordinates(&self, offsets: &[i64]) -> i64 {
+        let len = self.nodes.len() as i64;
+        let zero_pos = self.position(self.zero_idx) as i64;
+        offsets
+            .iter()
+            .map(|&a| {
+                let pos = (zero_pos + a).rem_euclid(len) as usize;
+                self.nodes[self.at_position(pos)].value
+            })
             .sum::<i64>()
     }
 }
@@ -127,17 +384,18 @@ fn part1(input: &str) -> Result<i64, Error> {
     let mut list = input.parse::<List>()?;
     list.mix();
 
-    Ok(list.score_coordinates())
+    Ok(list.score_coordinates(&COORDINATE_OFFSETS))
 }
 
 fn part2(input: &str) -> Result<i64, Error> {
-    let mut list = input.parse::<List>()?;
-    list.entries.iter_mut().for_each(|i| i.value *= 811589153);
+    // The large values and repeated mixing make walking the linked list too slow here
+    let mut list = IndexedList::from(input.parse::<List>()?);
+    list.nodes.iter_mut().for_each(|i| i.value *= 811589153);
     for _ in 0..10 {
         list.mix();
     }
 
-    Ok(list.score_coordinates())
+    Ok(list.score_coordinates(&COORDINATE_OFFSETS))
 }
 
 fn main() -> Result<(), Error> {
@@ -152,7 +410,167 @@ fn main() -> Result<(), Error> {
 #[cfg(test)]
 mod tests {
 
-    use crate::{part1, part2};
+    use crate::{part1, part2, IndexedList, List, ListEntry, Mixer, COORDINATE_OFFSETS};
+    use itertools::Itertools;
+
+    fn list_from_zero(list: &List) -> Vec<i64> {
+        list.mixer
+            .values_from(list.zero_idx)
+            .into_iter()
+            .copied()
+            .collect()
+    }
+
+    fn indexed_from_zero(list: &IndexedList) -> Vec<i64> {
+        let len = list.nodes.len();
+        let zero_pos = list.position(list.zero_idx);
+        (0..len)
+            .map(|i| list.nodes[list.at_position((zero_pos + i) % len)].value)
+            .collect()
+    }
+
+    fn assert_same_mix(input: &str, rounds: usize) {
+        let mut list = input.parse::<List>().unwrap();
+        let mut indexed = IndexedList::from(input.parse::<List>().unwrap());
+        for _ in 0..rounds {
+            list.mix();
+            list.mixer.check_invariants().unwrap();
+            indexed.mix();
+            assert_eq!(list_from_zero(&list), indexed_from_zero(&indexed));
+        }
+        assert_eq!(
+            list.score_coordinates(&COORDINATE_OFFSETS),
+            indexed.score_coordinates(&COORDINATE_OFFSETS)
+        );
+        assert_eq!(
+            list.score_coordinates(&[0, 7, 1]),
+            indexed.score_coordinates(&[0, 7, 1])
+        );
+    }
+
+    #[test]
+    fn custom_offsets() {
+        let mut list = TEST_INPUT.parse::<List>().unwrap();
+        list.mix();
+
+        assert_eq!(list.score_coordinates(&[1000, 2000, 3000]), 3);
+        assert_eq!(list.score_coordinates(&[1000]), 4);
+        assert_eq!(list.score_coordinates(&[0, 1]), 3);
+    }
+
+    #[test]
+    fn mixer_custom_offset() {
+        let values: Vec<i64> = TEST_INPUT.lines().map(|l| l.parse().unwrap()).collect();
+        let labelled = values.iter().map(|&v| (v, v.to_string())).collect_vec();
+
+        // Moving by the negated value is the same as mixing the negated list
+        let mut mixer = Mixer::new(labelled);
+        mixer.mix_by(|(v, _)| -v);
+        mixer.check_invariants().unwrap();
+        let negated = values.iter().map(|v| (-v).to_string()).join("\n");
+        let mut list = negated.parse::<List>().unwrap();
+        list.mix();
+
+        let mixed = mixer
+            .values_from(list.zero_idx)
+            .into_iter()
+            .map(|(v, _)| -v)
+            .collect_vec();
+        assert_eq!(mixed, list_from_zero(&list));
+        assert_eq!(mixer.values_from(0)[0].1, "1");
+    }
+
+    #[test]
+    fn invariants_after_mix() {
+        let mut list = TEST_INPUT.parse::<List>().unwrap();
+        list.mixer.check_invariants().unwrap();
+        list.mix();
+        list.mixer.check_invariants().unwrap();
+
+        // Break the back link of whatever follows entry 0
+        let next = list.mixer.entries[0].next;
+        list.mixer.entries[next].prev = next;
+        assert!(list.mixer.check_invariants().is_err());
+    }
+
+    // The example, but with the element originally at k stored in slot 3k mod 7, linked in the
+    // original order
+    fn shuffled_example() -> List {
+        let values: Vec<i64> = TEST_INPUT.lines().map(|l| l.parse().unwrap()).collect();
+        let len = values.len();
+
+        let slot = |k: usize| (k * 3) % len;
+        let mut entries = (0..len).map(|_| None).collect_vec();
+        for (k, &value) in values.iter().enumerate() {
+            entries[slot(k)] = Some(ListEntry {
+                value,
+                original_index: k,
+                prev: slot((k + len - 1) % len),
+                next: slot((k + 1) % len),
+            });
+        }
+
+        let zero = values.iter().position(|&v| v == 0).unwrap();
+        List {
+            zero_idx: slot(zero),
+            mixer: Mixer {
+                entries: entries.into_iter().map(Option::unwrap).collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn mix_order_independent_of_storage() {
+        let mut shuffled = shuffled_example();
+        shuffled.mixer.check_invariants().unwrap();
+
+        let mut list = TEST_INPUT.parse::<List>().unwrap();
+        list.mix();
+        shuffled.mix();
+        shuffled.mixer.check_invariants().unwrap();
+
+        assert_eq!(list_from_zero(&shuffled), list_from_zero(&list));
+    }
+
+    #[test]
+    fn indexed_mix_order_independent_of_storage() {
+        let mut list = TEST_INPUT.parse::<List>().unwrap();
+        list.mix();
+
+        let mut indexed = IndexedList::from(shuffled_example());
+        indexed.mix();
+        assert_eq!(indexed_from_zero(&indexed), list_from_zero(&list));
+
+        // Once mixed, list order no longer matches storage or original order either
+        let mut once = TEST_INPUT.parse::<List>().unwrap();
+        once.mix();
+        let mut indexed = IndexedList::from(once);
+        indexed.mix();
+        list.mix();
+        assert_eq!(indexed_from_zero(&indexed), list_from_zero(&list));
+    }
+
+    #[test]
+    fn indexed_mix_example() {
+        assert_same_mix(TEST_INPUT, 10);
+    }
+
+    #[test]
+    fn indexed_mix_random() {
+        // Fixed seed so a failure reproduces. Values up to 10x the length make most moves wrap
+        // around the list several times
+        let mut seed = 12345u64;
+        let input = (0..1000)
+            .map(|i| {
+                if i == 500 {
+                    return 0;
+                }
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                (seed >> 33) as i64 % 20000 - 10000
+            })
+            .join("\n");
+        assert_same_mix(&input, 3);
+    }
 
     #[test]
     fn part1_example() {
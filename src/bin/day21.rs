@@ -1,6 +1,10 @@
-use std::{collections::HashMap, fs};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+};
 
 use anyhow::{anyhow, bail, Context, Error};
+use itertools::Itertools;
 use nom::{
     bytes::complete::tag,
     character::complete::{alpha1, digit1, one_of},
@@ -10,7 +14,7 @@ use nom::{
 };
 
 enum Monkey<'a> {
-    Const(u64),
+    Const(i128),
     Operation {
         left: &'a str,
         right: &'a str,
@@ -26,43 +30,51 @@ enum Operation {
     Eq,
 }
 
+// Division by zero is reported as such, everything else out of range as overflow
+fn checked_div(a: i128, b: i128) -> Result<i128, Error> {
+    if b == 0 {
+        bail!("division by zero");
+    }
+    a.checked_div(b).context("arithmetic overflow")
+}
+
 impl Operation {
-    fn solve_for_left(&self, value: u64, right: u64) -> u64 {
+    fn solve_for_left(&self, value: i128, right: i128) -> Result<i128, Error> {
         match self {
-            Operation::Add => value - right,
-            Operation::Sub => value + right,
-            Operation::Mul => value / right,
-            Operation::Div => value * right,
-            Operation::Eq => right,
+            Operation::Add => value.checked_sub(right).context("arithmetic overflow"),
+            Operation::Sub => value.checked_add(right).context("arithmetic overflow"),
+            Operation::Mul => checked_div(value, right),
+            Operation::Div => value.checked_mul(right).context("arithmetic overflow"),
+            Operation::Eq => Ok(right),
         }
     }
 
-    fn solve_for_right(&self, value: u64, left: u64) -> u64 {
+    fn solve_for_right(&self, value: i128, left: i128) -> Result<i128, Error> {
         match self {
-            Operation::Add => value - left,
-            Operation::Sub => left - value,
-            Operation::Mul => value / left,
-            Operation::Div => left / value,
-            Operation::Eq => left,
+            Operation::Add => value.checked_sub(left).context("arithmetic overflow"),
+            Operation::Sub => left.checked_sub(value).context("arithmetic overflow"),
+            Operation::Mul => checked_div(value, left),
+            Operation::Div => checked_div(left, value),
+            Operation::Eq => Ok(left),
         }
     }
 
-    fn eval(&self, left: u64, right: u64) -> u64 {
+    fn eval(&self, left: i128, right: i128) -> Result<i128, Error> {
         match self {
-            Operation::Add => left + right,
-            Operation::Sub => left - right,
-            Operation::Mul => left * right,
-            Operation::Div => left / right,
+            Operation::Add => left.checked_add(right).context("arithmetic overflow"),
+            Operation::Sub => left.checked_sub(right).context("arithmetic overflow"),
+            Operation::Mul => left.checked_mul(right).context("arithmetic overflow"),
+            Operation::Div => checked_div(left, right),
             Operation::Eq => unimplemented!("cannot eval eq operation"),
         }
     }
 }
 
-fn parse_monkey(s: &str) -> Result<(&str, Monkey), Error> {
-    fn parse(s: &str) -> IResult<&str, (&str, Monkey)> {
+fn parse_monkey(s: &str) -> Result<(&str, Monkey<'_>), Error> {
+    fn parse(s: &str) -> IResult<&str, (&str, Monkey<'_>)> {
         let (s, name) = alpha1(s)?;
         let (s, _) = tag(": ")(s)?;
-        let (s, opt_const) = opt(map_res(digit1, |s: &str| s.parse::<u64>()))(s)?;
+        let (s, opt_const) = opt(map_res(digit1, |s: &str| s.parse::<i128>()))(s)?;
 
         if let Some(c) = opt_const {
             return Ok((s, (name, Monkey::Const(c))));
@@ -98,71 +110,179 @@ impl<'a> MonkeyCollection<'a> {
             .map(parse_monkey)
             .collect::<Result<HashMap<_, _>, _>>()?;
 
-        Ok(MonkeyCollection { monkeys })
+        let collection = MonkeyCollection { monkeys };
+        collection.topological_order()?;
+
+        Ok(collection)
+    }
+
+    // Kahn's algorithm, mostly to reject cyclic definitions before we recurse into them
+    fn topological_order(&self) -> Result<Vec<&'a str>, Error> {
+        let mut remaining_deps = HashMap::new();
+        let mut dependents: HashMap<&str, Vec<&str>> = HashMap::new();
+        let mut ready = Vec::new();
+
+        for (&name, monkey) in &self.monkeys {
+            match monkey {
+                Monkey::Const(_) => ready.push(name),
+                Monkey::Operation { left, right, op: _ } => {
+                    for dep in [left, right] {
+                        if !self.monkeys.contains_key(dep) {
+                            bail!("monkey '{}' depends on unknown monkey '{}'", name, dep);
+                        }
+                        dependents.entry(dep).or_default().push(name);
+                    }
+                    remaining_deps.insert(name, 2);
+                }
+            }
+        }
+
+        let mut order = Vec::with_capacity(self.monkeys.len());
+        while let Some(name) = ready.pop() {
+            order.push(name);
+            for &dependent in dependents.get(name).into_iter().flatten() {
+                let deps = remaining_deps
+                    .get_mut(dependent)
+                    .context("dependent monkey was not an operation")?;
+                *deps -= 1;
+                if *deps == 0 {
+                    ready.push(dependent);
+                }
+            }
+        }
+
+        if order.len() < self.monkeys.len() {
+            let cyclic = remaining_deps
+                .iter()
+                .filter(|(_, &deps)| deps > 0)
+                .map(|(&name, _)| name)
+                .sorted()
+                .join(", ");
+            bail!("monkey definitions contain a cycle involving: {}", cyclic);
+        }
+
+        Ok(order)
     }
 
-    fn get(&self, which: &str) -> Result<&Monkey, Error> {
+    fn get(&self, which: &str) -> Result<&Monkey<'_>, Error> {
         self.monkeys.get(which).context("could not find monkey")
     }
 
     // Will be called once on each monkey, literally no gain from memoizing
-    fn get_value(&self, which: &'a str) -> Result<u64, Error> {
+    fn get_value(&self, which: &'a str) -> Result<i128, Error> {
         match self.get(which)? {
             Monkey::Const(c) => Ok(*c),
             Monkey::Operation { left, right, op } => {
                 let left = self.get_value(left)?;
                 let right = self.get_value(right)?;
-                Ok(op.eval(left, right))
+                op.eval(left, right)
+                    .map_err(|e| anyhow!("{} evaluating '{}'", e, which))
             }
         }
     }
 
-    // Will be called multiple times on the same monkey, but not worth memoizing
-    fn has_human(&self, which: &'a str) -> Result<bool, Error> {
-        if which == "humn" {
-            return Ok(true);
+    // (distinct monkeys, longest chain of monkeys) below and including `which`. Depths are memoized
+    // in dependency order, so shared subtrees and long chains are cheap.
+    #[cfg(test)]
+    fn tree_stats(&self, which: &'a str) -> Result<(usize, usize), Error> {
+        self.get(which)?;
+
+        let mut nodes = HashSet::from([which]);
+        let mut stack = vec![which];
+        while let Some(name) = stack.pop() {
+            if let Monkey::Operation { left, right, op: _ } = &self.monkeys[name] {
+                for dep in [*left, *right] {
+                    if nodes.insert(dep) {
+                        stack.push(dep);
+                    }
+                }
+            }
         }
 
-        Ok(match self.get(which)? {
-            Monkey::Operation { left, right, op: _ } => {
-                self.has_human(left)? || self.has_human(right)?
+        let mut depths: HashMap<&str, usize> = HashMap::new();
+        for name in self.topological_order()? {
+            if !nodes.contains(name) {
+                continue;
             }
-            _ => false,
-        })
+            let depth = match &self.monkeys[name] {
+                Monkey::Const(_) => 1,
+                Monkey::Operation { left, right, op: _ } => 1 + depths[left].max(depths[right]),
+            };
+            depths.insert(name, depth);
+        }
+
+        Ok((nodes.len(), depths[which]))
+    }
+
+    // Plugs a candidate into humn, which stays there afterwards
+    #[cfg(test)]
+    fn root_branches_equal(&mut self, humn: i128) -> Result<bool, Error> {
+        self.monkeys.insert("humn", Monkey::Const(humn));
+
+        let Monkey::Operation { left, right, op: _ } = self.monkeys["root"] else {
+            bail!("root has no branches")
+        };
+
+        Ok(self.get_value(left)? == self.get_value(right)?)
     }
 
-    fn solve_for_human(&self, which: &'a str, value: u64) -> Result<u64, Error> {
-        if which == "humn" {
-            return Ok(value);
+    // Every monkey whose value depends on humn, humn included. One pass in dependency order, so
+    // each monkey is only looked at once.
+    fn human_ancestors(&self) -> Result<HashSet<&'a str>, Error> {
+        let mut with_human = HashSet::new();
+        for name in self.topological_order()? {
+            let has_human = match &self.monkeys[name] {
+                _ if name == "humn" => true,
+                Monkey::Operation { left, right, op: _ } => {
+                    with_human.contains(left) || with_human.contains(right)
+                }
+                Monkey::Const(_) => false,
+            };
+            if has_human {
+                with_human.insert(name);
+            }
         }
 
-        if let Monkey::Operation { left, right, op } = &self.monkeys[which] {
+        Ok(with_human)
+    }
+
+    fn solve_for_human(&self, mut which: &'a str, mut value: i128) -> Result<i128, Error> {
+        let with_human = self.human_ancestors()?;
+
+        while which != "humn" {
+            let Monkey::Operation { left, right, op } = &self.monkeys[which] else {
+                bail!("monkey has no subtree")
+            };
             let op = if which == "root" { &Operation::Eq } else { op };
-            let left_has_human = self.has_human(left)?;
-            debug_assert_eq!(self.has_human(right)?, !left_has_human);
 
-            if left_has_human {
+            if with_human.contains(left) {
                 let right = self.get_value(right)?;
-                let target_value = op.solve_for_left(value, right);
-                self.solve_for_human(left, target_value)
-            } else {
+                value = op
+                    .solve_for_left(value, right)
+                    .map_err(|e| anyhow!("{} inverting '{}'", e, which))?;
+                which = left;
+            } else if with_human.contains(right) {
                 let left = self.get_value(left)?;
-                let target_value = op.solve_for_right(value, left);
-                self.solve_for_human(right, target_value)
+                value = op
+                    .solve_for_right(value, left)
+                    .map_err(|e| anyhow!("{} inverting '{}'", e, which))?;
+                which = right;
+            } else {
+                bail!("monkey '{}' does not depend on humn", which);
             }
-        } else {
-            bail!("monkey has no subtree")
         }
+
+        Ok(value)
     }
 }
 
-fn part1(input: &str) -> Result<u64, Error> {
+fn part1(input: &str) -> Result<i128, Error> {
     let monkeys = MonkeyCollection::parse_input(input)?;
 
     monkeys.get_value("root")
 }
 
-fn part2(input: &str) -> Result<u64, Error> {
+fn part2(input: &str) -> Result<i128, Error> {
     let monkeys = MonkeyCollection::parse_input(input)?;
 
     let root_value = monkeys.get_value("root")?;
@@ -182,7 +302,7 @@ fn main() -> Result<(), Error> {
 #[cfg(test)]
 mod tests {
 
-    use crate::{part1, part2};
+    use crate::{part1, part2, MonkeyCollection};
 
     #[test]
     fn part1_example() {
@@ -194,6 +314,98 @@ mod tests {
         assert_eq!(part2(TEST_INPUT).unwrap(), 301);
     }
 
+    #[test]
+    fn plug_in_solution() {
+        let mut monkeys = MonkeyCollection::parse_input(TEST_INPUT).unwrap();
+        assert!(monkeys.root_branches_equal(301).unwrap());
+        assert!(!monkeys.root_branches_equal(305).unwrap());
+        assert!(!monkeys.root_branches_equal(300).unwrap());
+    }
+
+    #[test]
+    fn example_tree_stats() {
+        let monkeys = MonkeyCollection::parse_input(TEST_INPUT).unwrap();
+        // root -> pppw -> cczh -> lgvd -> ptdq -> humn
+        assert_eq!(monkeys.tree_stats("root").unwrap(), (15, 6));
+        assert_eq!(monkeys.tree_stats("sjmn").unwrap(), (5, 3));
+        assert_eq!(monkeys.tree_stats("humn").unwrap(), (1, 1));
+        assert!(monkeys.tree_stats("nope").is_err());
+    }
+
+    #[test]
+    fn beyond_u64() {
+        let input = "root: aaaa * bbbb\naaaa: 10000000000\nbbbb: 10000000000\n";
+        assert_eq!(part1(input).unwrap(), 100000000000000000000);
+        assert!(100000000000000000000 > u64::MAX as i128);
+
+        let input = "root: aaaa * bbbb\naaaa: 100000000000000000000\nbbbb: 100000000000000000000\n";
+        assert_eq!(
+            part1(input).err().unwrap().to_string(),
+            "arithmetic overflow evaluating 'root'"
+        );
+    }
+
+    #[test]
+    fn division_by_zero() {
+        let input = "root: aaaa / bbbb\naaaa: 10\nbbbb: cccc - dddd\ncccc: 3\ndddd: 3\n";
+        assert_eq!(
+            part1(input).err().unwrap().to_string(),
+            "division by zero evaluating 'root'"
+        );
+
+        // humn * 0 can't be inverted
+        let input = "root: aaaa + bbbb\naaaa: humn * zero\nzero: 0\nhumn: 5\nbbbb: 7\n";
+        assert_eq!(
+            part2(input).err().unwrap().to_string(),
+            "division by zero inverting 'aaaa'"
+        );
+    }
+
+    #[test]
+    fn deep_human_chain() {
+        let name = |i: usize| {
+            format!(
+                "x{}{}",
+                (b'a' + (i / 26) as u8) as char,
+                (b'a' + (i % 26) as u8) as char
+            )
+        };
+        let depth = 300;
+
+        let mut input = format!("root: {} + target\ntarget: 1000\none: 1\n", name(0));
+        for i in 0..depth {
+            // Alternate which side humn is on
+            let next = if i + 1 == depth {
+                "humn".to_string()
+            } else {
+                name(i + 1)
+            };
+            if i % 2 == 0 {
+                input += &format!("{}: {} + one\n", name(i), next);
+            } else {
+                input += &format!("{}: one + {}\n", name(i), next);
+            }
+        }
+        input += "humn: 5\n";
+
+        assert_eq!(part2(&input).unwrap(), 1000 - depth as i128);
+
+        let monkeys = MonkeyCollection::parse_input(&input).unwrap();
+        let with_human = monkeys.human_ancestors().unwrap();
+        assert_eq!(with_human.len(), depth + 2);
+        assert!(!with_human.contains("target"));
+    }
+
+    #[test]
+    fn cyclic_definition() {
+        let input = "root: aaaa + cccc\naaaa: bbbb + cccc\nbbbb: aaaa + cccc\ncccc: 1\n";
+        let err = MonkeyCollection::parse_input(input).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "monkey definitions contain a cycle involving: aaaa, bbbb, root"
+        );
+    }
+
     static TEST_INPUT: &str = "root: pppw + sjmn
 dbpl: 5
 cczh: sllz + lgvd